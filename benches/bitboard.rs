@@ -0,0 +1,60 @@
+// This file is part of the samson library.
+//
+// Copyright (C) 2017 Lakin Wecker <lakin@wecker.ca>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+#![feature(test)]
+
+extern crate test;
+extern crate samson;
+
+use test::Bencher;
+use samson::types::Bitboard;
+
+fn sample_boards() -> Vec<Bitboard> {
+    (0u64..1024).map(|i| Bitboard(i.wrapping_mul(0x9E3779B97F4A7C15))).collect()
+}
+
+#[bench]
+fn bench_popcount(b: &mut Bencher) {
+    let boards = sample_boards();
+    b.iter(|| {
+        let mut total = 0u32;
+        for &bb in &boards {
+            total = total.wrapping_add(bb.popcount());
+        }
+        test::black_box(total)
+    });
+}
+
+#[bench]
+fn bench_lsb(b: &mut Bencher) {
+    let boards: Vec<Bitboard> = sample_boards().into_iter().filter(|bb| !bb.is_empty()).collect();
+    b.iter(|| {
+        for &bb in &boards {
+            test::black_box(bb.lsb());
+        }
+    });
+}
+
+#[bench]
+fn bench_msb(b: &mut Bencher) {
+    let boards: Vec<Bitboard> = sample_boards().into_iter().filter(|bb| !bb.is_empty()).collect();
+    b.iter(|| {
+        for &bb in &boards {
+            test::black_box(bb.msb());
+        }
+    });
+}
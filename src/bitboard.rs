@@ -20,6 +20,9 @@
 use super::types::*;
 use std::ops::*;
 use std::cmp::{max};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+pub mod magic;
 
 // De Bruijn sequences. See chessprogramming.wikispaces.com/BitScan
 pub const DEBRUIJN_64: u64 = 0x3F79D71B4CB0A89u64;
@@ -73,6 +76,236 @@ pub fn more_than_one(b: Bitboard) -> bool {
   return b.0 & (b.0 - 1) != 0
 }
 
+///-----------------------------------------------------------------------------
+// Runtime CPU-feature detection, mirroring Stockfish's `HasPopCnt`/`HasPext`:
+// checked once via `is_x86_feature_detected!` and cached in an atomic, rather
+// than re-checked on every `popcount`/`lsb`/`msb`/`pext_dispatch` call.
+
+const FEATURE_UNKNOWN: u8 = 0;
+const FEATURE_ABSENT: u8 = 1;
+const FEATURE_PRESENT: u8 = 2;
+
+static POPCNT_FLAG: AtomicU8 = AtomicU8::new(FEATURE_UNKNOWN);
+static BMI2_FLAG: AtomicU8 = AtomicU8::new(FEATURE_UNKNOWN);
+
+/// Whether this CPU has hardware `popcnt`.
+#[cfg(target_arch = "x86_64")]
+fn has_popcnt() -> bool {
+    match POPCNT_FLAG.load(Ordering::Relaxed) {
+        FEATURE_PRESENT => true,
+        FEATURE_ABSENT => false,
+        _ => {
+            let present = is_x86_feature_detected!("popcnt");
+            POPCNT_FLAG.store(if present { FEATURE_PRESENT } else { FEATURE_ABSENT }, Ordering::Relaxed);
+            present
+        }
+    }
+}
+#[cfg(not(target_arch = "x86_64"))]
+fn has_popcnt() -> bool {
+    false
+}
+
+/// Whether this CPU has hardware `BMI2` (and so the `pext` instruction).
+#[cfg(target_arch = "x86_64")]
+fn has_bmi2() -> bool {
+    match BMI2_FLAG.load(Ordering::Relaxed) {
+        FEATURE_PRESENT => true,
+        FEATURE_ABSENT => false,
+        _ => {
+            let present = is_x86_feature_detected!("bmi2");
+            BMI2_FLAG.store(if present { FEATURE_PRESENT } else { FEATURE_ABSENT }, Ordering::Relaxed);
+            present
+        }
+    }
+}
+#[cfg(not(target_arch = "x86_64"))]
+fn has_bmi2() -> bool {
+    false
+}
+
+/// Software fallback for `msb`: smear every bit below the highest set bit
+/// down to bit 0, isolate that highest bit by subtracting the smear
+/// shifted right one, then look its position up through the same
+/// De Bruijn table `lsb` uses.
+fn msb_index(b: Bitboard) -> Square {
+    let mut v = b.0;
+    v |= v >> 1;
+    v |= v >> 2;
+    v |= v >> 4;
+    v |= v >> 8;
+    v |= v >> 16;
+    v |= v >> 32;
+    v -= v >> 1;
+    BSF_TABLE[bsf_index(Bitboard(v))]
+}
+
+impl Bitboard {
+    /// The bitboard with only `s` set.
+    pub fn from_square(s: Square) -> Bitboard {
+        SQUARE_BB[s.0 as usize]
+    }
+
+    /// Count of set squares: the hardware `popcnt` instruction when
+    /// `has_popcnt()` finds it, otherwise the `POPCNT_16` SWAR/lookup-table
+    /// fallback.
+    pub fn popcount(self) -> u32 {
+        if has_popcnt() {
+            return self.0.count_ones();
+        }
+        let b = self.0;
+        POPCNT_16[(b & 0xFFFF) as usize] as u32
+            + POPCNT_16[((b >> 16) & 0xFFFF) as usize] as u32
+            + POPCNT_16[((b >> 32) & 0xFFFF) as usize] as u32
+            + POPCNT_16[((b >> 48) & 0xFFFF) as usize] as u32
+    }
+
+    /// The least-significant set square: the hardware bit-scan when
+    /// `has_popcnt()` finds it, otherwise the De Bruijn `BSF_TABLE`
+    /// lookup. On an empty bitboard the hardware path gives `SQ_NONE`
+    /// (`trailing_zeros` of 0 is 64); the table path is undefined there,
+    /// as in Stockfish -- callers are expected to check emptiness
+    /// themselves before calling.
+    pub fn lsb(self) -> Square {
+        if has_popcnt() {
+            Square(self.0.trailing_zeros() as i8)
+        } else {
+            BSF_TABLE[bsf_index(self)]
+        }
+    }
+
+    /// The most-significant set square: the hardware bit-scan when
+    /// `has_popcnt()` finds it, otherwise `msb_index`'s table lookup.
+    /// Undefined on an empty bitboard.
+    pub fn msb(self) -> Square {
+        if has_popcnt() {
+            Square(63 - self.0.leading_zeros() as i8)
+        } else {
+            msb_index(self)
+        }
+    }
+
+    /// Clear and return the least-significant set square.
+    pub fn pop_lsb(&mut self) -> Square {
+        let s = self.lsb();
+        self.0 &= self.0 - 1;
+        s
+    }
+
+    /// Is every square clear?
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Shift every set square one step in `direction` (one of the
+    /// `Square` deltas `NORTH`/`SOUTH`/`EAST`/`WEST`/the four diagonals),
+    /// masking off the file that would otherwise wrap around the board
+    /// edge.
+    pub fn shift(self, direction: Square) -> Bitboard {
+        match direction {
+            NORTH => Bitboard(self.0 << 8),
+            SOUTH => Bitboard(self.0 >> 8),
+            EAST => Bitboard((self.0 & !FILE_HBB.0) << 1),
+            WEST => Bitboard((self.0 & !FILE_ABB.0) >> 1),
+            NORTH_EAST => Bitboard((self.0 & !FILE_HBB.0) << 9),
+            NORTH_WEST => Bitboard((self.0 & !FILE_ABB.0) << 7),
+            SOUTH_EAST => Bitboard((self.0 & !FILE_HBB.0) >> 7),
+            SOUTH_WEST => Bitboard((self.0 & !FILE_ABB.0) >> 9),
+            _ => Bitboard(0),
+        }
+    }
+}
+
+/// Enumerates the set squares of a `Bitboard`, least-significant first,
+/// consuming each via `pop_lsb`.
+pub struct BitboardIter(Bitboard);
+
+impl Iterator for BitboardIter {
+    type Item = Square;
+    fn next(&mut self) -> Option<Square> {
+        if self.0 == Bitboard(0) {
+            None
+        } else {
+            Some(self.0.pop_lsb())
+        }
+    }
+}
+
+impl IntoIterator for Bitboard {
+    type Item = Square;
+    type IntoIter = BitboardIter;
+    fn into_iter(self) -> BitboardIter {
+        BitboardIter(self)
+    }
+}
+
+/// The (file, rank) unit step from `s1` toward `s2` if they share a
+/// rank, file, or diagonal; `None` if they don't, or if `s1 == s2`.
+fn ray_delta(s1: Square, s2: Square) -> Option<(i8, i8)> {
+    let df = file_of(s2).0 - file_of(s1).0;
+    let dr = rank_of(s2).0 - rank_of(s1).0;
+    if df == 0 && dr == 0 {
+        return None;
+    }
+    if df != 0 && dr != 0 && df.abs() != dr.abs() {
+        return None;
+    }
+    Some((df.signum(), dr.signum()))
+}
+
+/// Walk the board from `(start_file, start_rank)` one step at a time in
+/// direction `(df, dr)`, stopping at the edge, and OR every square
+/// visited (not including the start) into a `Bitboard`.
+fn ray_bb(start_file: i8, start_rank: i8, df: i8, dr: i8) -> Bitboard {
+    let mut bb = Bitboard(0);
+    let mut f = start_file + df;
+    let mut r = start_rank + dr;
+    while f >= 0 && f < 8 && r >= 0 && r < 8 {
+        bb |= make_square(File(f), Rank(r));
+        f += df;
+        r += dr;
+    }
+    bb
+}
+
+/// Every square strictly between `s1` and `s2` when they're aligned on a
+/// rank, file, or diagonal; empty otherwise.
+fn between_bb(s1: Square, s2: Square) -> Bitboard {
+    match ray_delta(s1, s2) {
+        None => Bitboard(0),
+        Some((df, dr)) => {
+            let mut bb = Bitboard(0);
+            let mut f = file_of(s1).0 + df;
+            let mut r = rank_of(s1).0 + dr;
+            loop {
+                let sq = make_square(File(f), Rank(r));
+                if sq == s2 {
+                    break;
+                }
+                bb |= sq;
+                f += df;
+                r += dr;
+            }
+            bb
+        }
+    }
+}
+
+/// The full rank, file, or diagonal line passing through both `s1` and
+/// `s2`, from edge to edge; empty if they aren't aligned.
+fn line_bb(s1: Square, s2: Square) -> Bitboard {
+    match ray_delta(s1, s2) {
+        None => Bitboard(0),
+        Some((df, dr)) => {
+            let start_file = file_of(s1).0;
+            let start_rank = rank_of(s1).0;
+            Bitboard::from_square(s1) | Bitboard::from_square(s2)
+                | ray_bb(start_file, start_rank, df, dr)
+                | ray_bb(start_file, start_rank, -df, -dr)
+        }
+    }
+}
+
 lazy_static! {
     pub static ref POPCNT_16: [u8; 1<<16] = {
         let mut popcnt_16 = [0; 1<<16];
@@ -81,33 +314,68 @@ lazy_static! {
         }
         popcnt_16
     };
-    /// TODO: this is an optimization anyways.
-    /*
-    pub static ref SQUARE_DISTANCE: &'static [[i32; SQUARE_NB]; SQUARE_NB] = {
-        let mut square_distance = [[0; SQUARE_NB]; SQUARE_NB];
-        for s1 in (SQ_A1.0)..(SQ_H8.0) {
-            for s2 in (SQ_A1.0)..(SQ_H8.0) {
-                if s1 != s2 {
-                    square_distance[s1][s2] = max();
-                }
+    /// Chebyshev distance between every pair of squares -- the number of
+    /// king moves it takes to get from one to the other.
+    pub static ref SQUARE_DISTANCE: [[i32; SQUARE_NB_USIZE]; SQUARE_NB_USIZE] = {
+        let mut square_distance = [[0; SQUARE_NB_USIZE]; SQUARE_NB_USIZE];
+        for s1 in 0..SQUARE_NB_USIZE {
+            for s2 in 0..SQUARE_NB_USIZE {
+                let file_distance = (file_of(Square(s1 as i8)).0 - file_of(Square(s2 as i8)).0).abs();
+                let rank_distance = (rank_of(Square(s1 as i8)).0 - rank_of(Square(s2 as i8)).0).abs();
+                square_distance[s1][s2] = max(file_distance, rank_distance) as i32;
             }
         }
         square_distance
-    };*/
+    };
     pub static ref SQUARE_BB: [Bitboard; 64] = {
         let mut square_bb = [Bitboard(0); 64];
-        for s in (SQ_A1.0)..(SQ_H8.0) {
+        for s in (SQ_A1.0)..(SQ_H8.0 + 1) {
             square_bb[s as usize] = Bitboard(1u64 << s);
         }
         square_bb
     };
     pub static ref BSF_TABLE: [Square; 64] = {
         let mut bsf_table = [Square(0); 64];
-        for s in (SQ_A1.0)..(SQ_H8.0) {
+        for s in (SQ_A1.0)..(SQ_H8.0 + 1) {
             bsf_table[bsf_index(SQUARE_BB[s as usize])] = Square(s);
         }
         bsf_table
     };
+    /// `DISTANCE_RING_BB[s][d]` holds every square at Chebyshev distance
+    /// `d + 1` from `s` -- ring 0 is the squares one king step away,
+    /// ring 1 two steps, and so on.
+    pub static ref DISTANCE_RING_BB: [[Bitboard; 8]; SQUARE_NB_USIZE] = {
+        let mut rings = [[Bitboard(0); 8]; SQUARE_NB_USIZE];
+        for s1 in 0..SQUARE_NB_USIZE {
+            for s2 in 0..SQUARE_NB_USIZE {
+                if s1 != s2 {
+                    let d = SQUARE_DISTANCE[s1][s2] as usize;
+                    rings[s1][d - 1] |= Square(s2 as i8);
+                }
+            }
+        }
+        rings
+    };
+    /// `BETWEEN_BB[s1][s2]`: see `between_bb`.
+    pub static ref BETWEEN_BB: [[Bitboard; SQUARE_NB_USIZE]; SQUARE_NB_USIZE] = {
+        let mut table = [[Bitboard(0); SQUARE_NB_USIZE]; SQUARE_NB_USIZE];
+        for s1 in 0..SQUARE_NB_USIZE {
+            for s2 in 0..SQUARE_NB_USIZE {
+                table[s1][s2] = between_bb(Square(s1 as i8), Square(s2 as i8));
+            }
+        }
+        table
+    };
+    /// `LINE_BB[s1][s2]`: see `line_bb`.
+    pub static ref LINE_BB: [[Bitboard; SQUARE_NB_USIZE]; SQUARE_NB_USIZE] = {
+        let mut table = [[Bitboard(0); SQUARE_NB_USIZE]; SQUARE_NB_USIZE];
+        for s1 in 0..SQUARE_NB_USIZE {
+            for s2 in 0..SQUARE_NB_USIZE {
+                table[s1][s2] = line_bb(Square(s1 as i8), Square(s2 as i8));
+            }
+        }
+        table
+    };
 
     /*
     pub Bitboard FileBB[FILE_NB];
@@ -115,15 +383,34 @@ lazy_static! {
     pub Bitboard AdjacentFilesBB[FILE_NB];
     pub Bitboard InFrontBB[COLOR_NB][RANK_NB];
     pub Bitboard StepAttacksBB[PIECE_NB][SQUARE_NB];
-    pub Bitboard BetweenBB[SQUARE_NB][SQUARE_NB];
-    pub Bitboard LineBB[SQUARE_NB][SQUARE_NB];
-    pub Bitboard DistanceRingBB[SQUARE_NB][8];
     pub Bitboard ForwardBB[COLOR_NB][SQUARE_NB];
     pub Bitboard PassedPawnMask[COLOR_NB][SQUARE_NB];
     pub Bitboard PawnAttackSpan[COLOR_NB][SQUARE_NB];
     pub Bitboard PseudoAttacks[PIECE_TYPE_NB][SQUARE_NB];*/
 
 }
+
+/// O(1) Chebyshev distance between `s1` and `s2` (king-move count).
+pub fn distance(s1: Square, s2: Square) -> i32 {
+    SQUARE_DISTANCE[s1.0 as usize][s2.0 as usize]
+}
+
+/// Every square at Chebyshev distance `d` (1-7) from `s`.
+pub fn distance_ring(s: Square, d: i32) -> Bitboard {
+    DISTANCE_RING_BB[s.0 as usize][(d - 1) as usize]
+}
+
+/// Every square strictly between `s1` and `s2` if they share a rank,
+/// file, or diagonal; empty otherwise.
+pub fn between(s1: Square, s2: Square) -> Bitboard {
+    BETWEEN_BB[s1.0 as usize][s2.0 as usize]
+}
+
+/// The full rank, file, or diagonal line through both `s1` and `s2`;
+/// empty if they don't share one.
+pub fn line(s1: Square, s2: Square) -> Bitboard {
+    LINE_BB[s1.0 as usize][s2.0 as usize]
+}
 impl BitAnd<Square> for Bitboard {
     type Output = Self;
     fn bitand(self, s: Square) -> Bitboard { self & SQUARE_BB[s.0 as usize] }
@@ -155,6 +442,296 @@ inline bool more_than_one(Bitboard b) {
 */
 
 
+///-----------------------------------------------------------------------------
+// Leaper attack generation (knight, king).
+//
+// Each of the eight knight deltas (+-6, +-10, +-15, +-17) is just three
+// single-step `shift`s composed -- e.g. +17 is north-north-east.
+// Composing the already-edge-masked single steps keeps the wraparound
+// masking correct at every intermediate step, so there's no need for a
+// separate multi-square mask.
+
+/// Knight's-move attacks from `sq`.
+pub fn knight_attacks(sq: Square) -> Bitboard {
+    let b = Bitboard::from_square(sq);
+    b.shift(NORTH).shift(NORTH).shift(EAST)
+        | b.shift(NORTH).shift(NORTH).shift(WEST)
+        | b.shift(NORTH).shift(EAST).shift(EAST)
+        | b.shift(NORTH).shift(WEST).shift(WEST)
+        | b.shift(SOUTH).shift(EAST).shift(EAST)
+        | b.shift(SOUTH).shift(WEST).shift(WEST)
+        | b.shift(SOUTH).shift(SOUTH).shift(EAST)
+        | b.shift(SOUTH).shift(SOUTH).shift(WEST)
+}
+
+/// One-step attacks in all eight directions from `sq`.
+pub fn king_attacks(sq: Square) -> Bitboard {
+    let b = Bitboard::from_square(sq);
+    b.shift(NORTH) | b.shift(SOUTH) | b.shift(EAST) | b.shift(WEST)
+        | b.shift(NORTH_EAST) | b.shift(NORTH_WEST) | b.shift(SOUTH_EAST) | b.shift(SOUTH_WEST)
+}
+
+/// The king's castling destination square(s) for `color` given `rights`,
+/// so they can be OR'd alongside `king_attacks`/`king_attacks_from` when
+/// generating the king's full move set. Uses the standard (non-Chess960)
+/// `castling_king_target` lookup; Chess960 destinations are always g/c
+/// file too, so this still applies there.
+pub fn castling_target(color: Color, rights: CastleRights) -> Bitboard {
+    let mut targets = Bitboard(0);
+    if rights == CastleRights::KingSide || rights == CastleRights::BothSides {
+        targets |= Bitboard::from_square(castling_king_target(make_castling(color, KING_SIDE)));
+    }
+    if rights == CastleRights::QueenSide || rights == CastleRights::BothSides {
+        targets |= Bitboard::from_square(castling_king_target(make_castling(color, QUEEN_SIDE)));
+    }
+    targets
+}
+
+lazy_static! {
+    /// `knight_attacks`, precomputed once for every square.
+    pub static ref KNIGHT_ATTACKS: [Bitboard; 64] = {
+        let mut table = [Bitboard(0); 64];
+        for s in (SQ_A1.0)..(SQ_H8.0 + 1) {
+            table[s as usize] = knight_attacks(Square(s));
+        }
+        table
+    };
+    /// `king_attacks`, precomputed once for every square.
+    pub static ref KING_ATTACKS: [Bitboard; 64] = {
+        let mut table = [Bitboard(0); 64];
+        for s in (SQ_A1.0)..(SQ_H8.0 + 1) {
+            table[s as usize] = king_attacks(Square(s));
+        }
+        table
+    };
+}
+
+/// O(1) lookup of `knight_attacks(sq)` from the precomputed table.
+pub fn knight_attacks_from(sq: Square) -> Bitboard {
+    KNIGHT_ATTACKS[sq.0 as usize]
+}
+
+/// O(1) lookup of `king_attacks(sq)` from the precomputed table.
+pub fn king_attacks_from(sq: Square) -> Bitboard {
+    KING_ATTACKS[sq.0 as usize]
+}
+
+///-----------------------------------------------------------------------------
+// Pawn move/capture generation.
+//
+// Parameterized by `Color` instead of branched per-call, the way
+// Stockfish's movegen picks its `Up`/`TRank3BB` template arguments once
+// and shares the rest of the code between colors.
+
+/// Per-color constants `pawn_pushes`/`pawn_captures` are built from:
+/// the forward direction, the relative third rank (where a single push
+/// must land for a double push to still be possible), and this color's
+/// promotion rank (a pawn already sitting there is an illegal position).
+struct PawnParams {
+    push: Square,
+    rank3: Bitboard,
+    rank8: Bitboard,
+}
+
+fn pawn_params(c: Color) -> PawnParams {
+    match c {
+        WHITE => PawnParams { push: NORTH, rank3: RANK_3BB, rank8: RANK_8BB },
+        _ => PawnParams { push: SOUTH, rank3: RANK_6BB, rank8: RANK_1BB },
+    }
+}
+
+/// Single and (when available) double forward pushes for a `color` pawn
+/// on `sq` against `occupied`. The double push additionally requires the
+/// intermediate square (relative rank 3) to be empty, which falls out of
+/// masking the single-push result by `rank3` before shifting it again.
+pub fn pawn_pushes(color: Color, sq: Square, occupied: Bitboard) -> Bitboard {
+    let params = pawn_params(color);
+    let pawn = Bitboard::from_square(sq);
+    if !(pawn & params.rank8).is_empty() {
+        return Bitboard(0);
+    }
+
+    let empty = !occupied;
+    let single = pawn.shift(params.push) & empty;
+    let double = (single & params.rank3).shift(params.push) & empty;
+    single | double
+}
+
+/// The two diagonal capture squares for a `color` pawn on `sq`: one step
+/// forward, then one step east or west, each edge-masked by `shift` so
+/// the A/H files don't wrap.
+pub fn pawn_captures(color: Color, sq: Square) -> Bitboard {
+    let params = pawn_params(color);
+    let pawn = Bitboard::from_square(sq);
+    if !(pawn & params.rank8).is_empty() {
+        return Bitboard(0);
+    }
+
+    let advanced = pawn.shift(params.push);
+    advanced.shift(EAST) | advanced.shift(WEST)
+}
+
+///-----------------------------------------------------------------------------
+// Sliding-piece attack generation.
+//
+// `sliding_attack` is the classical ray-by-ray generator: the ground
+// truth every square's precomputed table is built from. Looking it up at
+// search time is too slow, so each square instead gets a table indexed
+// by a compressed encoding of "which relevant-occupancy squares are
+// occupied" -- here, Haswell's PEXT instruction, via `pext_dispatch`
+// below (the hardware instruction when `has_bmi2()` finds it, a software
+// emulation otherwise). `attacks_bb` always reads these PEXT tables. The
+// `magic` submodule provides an equivalent multiplier-based index, used
+// only through its own `rook_attacks`/`bishop_attacks`/`queen_attacks`.
+
+/// Which file `s` sits on, as a bitboard.
+pub fn file_bb(s: Square) -> Bitboard {
+    Bitboard(FILE_ABB.0 << file_of(s).0)
+}
+
+/// Which rank `s` sits on, as a bitboard.
+pub fn rank_bb(s: Square) -> Bitboard {
+    Bitboard(RANK_1BB.0 << (8 * rank_of(s).0))
+}
+
+/// Software fallback for the BMI2 `pext` instruction: extract the bits
+/// of `b` selected by `mask`, packed down to the low end in mask-bit
+/// order.
+fn pext(b: u64, mask: u64) -> u64 {
+    let mut result = 0u64;
+    let mut bit = 1u64;
+    let mut m = mask;
+    while m != 0 {
+        let lsb = m & m.wrapping_neg();
+        if b & lsb != 0 {
+            result |= bit;
+        }
+        bit <<= 1;
+        m &= m - 1;
+    }
+    result
+}
+
+/// Hardware hook for `pext`. `#[target_feature]` functions are `unsafe`
+/// to call because the compiler can't check the CPU actually supports
+/// the feature -- that's on the caller, which is why this is only ever
+/// reached from behind a `has_bmi2()` check in `pext_dispatch`.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "bmi2")]
+unsafe fn pext_bmi2(b: u64, mask: u64) -> u64 {
+    ::std::arch::x86_64::_pext_u64(b, mask)
+}
+
+/// Extract the bits of `b` selected by `mask`, packed down to the low
+/// end in mask-bit order: the hardware `pext` instruction when
+/// `has_bmi2()` finds it, otherwise the software fallback above. Exposed
+/// (not just used by `PextEntry`) so any other occupancy-indexed table --
+/// e.g. a future `magic` submodule variant -- can share the same dispatch.
+pub fn pext_dispatch(b: u64, mask: u64) -> u64 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if has_bmi2() {
+            return unsafe { pext_bmi2(b, mask) };
+        }
+    }
+    pext(b, mask)
+}
+
+/// Ray-trace `pt`'s (rook or bishop) attacks from `sq` against
+/// `occupied`, stopping at and including the first blocker in each
+/// direction.
+fn sliding_attack(pt: PieceType, sq: Square, occupied: Bitboard) -> Bitboard {
+    let deltas: [(i8, i8); 4] = if pt == ROOK {
+        [(1, 0), (-1, 0), (0, 1), (0, -1)]
+    } else {
+        [(1, 1), (1, -1), (-1, 1), (-1, -1)]
+    };
+    let mut attack = Bitboard(0);
+    let start_file = file_of(sq).0;
+    let start_rank = rank_of(sq).0;
+    for &(df, dr) in deltas.iter() {
+        let mut f = start_file + df;
+        let mut r = start_rank + dr;
+        while f >= 0 && f < 8 && r >= 0 && r < 8 {
+            let s = make_square(File(f), Rank(r));
+            attack |= s;
+            if occupied & s != Bitboard(0) {
+                break;
+            }
+            f += df;
+            r += dr;
+        }
+    }
+    attack
+}
+
+/// One square's compressed attack table: `mask` is its relevant
+/// occupancy (every square, in any of its rays, that could block it --
+/// excluding the board edge, since a ray simply ends there regardless of
+/// what's "on" it), and `attacks[pext(occupied, mask)]` is the resulting
+/// attack set for that occupancy.
+pub struct PextEntry {
+    pub mask: Bitboard,
+    pub attacks: Vec<Bitboard>,
+}
+
+impl PextEntry {
+    fn index(&self, occupied: Bitboard) -> usize {
+        pext_dispatch(occupied.0, self.mask.0) as usize
+    }
+}
+
+/// Build the 64-square PEXT table for `pt` (`ROOK` or `BISHOP`) by
+/// enumerating every subset of each square's relevant-occupancy mask
+/// (the standard "carry-rippler" trick: `next = (cur - mask) & mask`,
+/// which visits every subset exactly once and returns to 0 last).
+fn init_pext_table(pt: PieceType) -> Vec<PextEntry> {
+    let mut table = Vec::with_capacity(SQUARE_NB_USIZE);
+    for sq in 0..SQUARE_NB_USIZE {
+        let square = Square(sq as i8);
+        let edges = ((RANK_1BB | RANK_8BB) & !rank_bb(square))
+            | ((FILE_ABB | FILE_HBB) & !file_bb(square));
+        let mask = sliding_attack(pt, square, Bitboard(0)) & !edges;
+        let size = 1usize << mask.0.count_ones();
+        let mut attacks = vec![Bitboard(0); size];
+
+        let mut occupied = Bitboard(0);
+        loop {
+            let idx = pext(occupied.0, mask.0) as usize;
+            attacks[idx] = sliding_attack(pt, square, occupied);
+            occupied = Bitboard(occupied.0.wrapping_sub(mask.0) & mask.0);
+            if occupied == Bitboard(0) {
+                break;
+            }
+        }
+        table.push(PextEntry { mask: mask, attacks: attacks });
+    }
+    table
+}
+
+lazy_static! {
+    pub static ref ROOK_PEXT: Vec<PextEntry> = init_pext_table(ROOK);
+    pub static ref BISHOP_PEXT: Vec<PextEntry> = init_pext_table(BISHOP);
+}
+
+/// Sliding attacks for `pt` (`ROOK`, `BISHOP`, or `QUEEN`) from `sq`
+/// against `occupied`. Leaper attacks (pawn/knight/king) aren't sliders
+/// and aren't handled here.
+pub fn attacks_bb(pt: PieceType, sq: Square, occupied: Bitboard) -> Bitboard {
+    match pt {
+        ROOK => {
+            let entry = &ROOK_PEXT[sq.0 as usize];
+            entry.attacks[entry.index(occupied)]
+        }
+        BISHOP => {
+            let entry = &BISHOP_PEXT[sq.0 as usize];
+            entry.attacks[entry.index(occupied)]
+        }
+        QUEEN => attacks_bb(ROOK, sq, occupied) | attacks_bb(BISHOP, sq, occupied),
+        _ => Bitboard(0),
+    }
+}
+
 ///-----------------------------------------------------------------------------
 #[cfg(test)]
 mod tests {
@@ -215,4 +792,206 @@ mod tests {
         assert_eq!(true, more_than_one(Bitboard(0b1110)));
         assert_eq!(true, more_than_one(Bitboard(0b1111)));
     }
+
+    #[test]
+    fn test_knight_attacks_corner() {
+        // A knight on A1 can only reach B3 and C2.
+        let attacks = knight_attacks(SQ_A1);
+        assert_eq!(Bitboard::from_square(SQ_B3) | Bitboard::from_square(SQ_C2), attacks);
+    }
+
+    #[test]
+    fn test_knight_attacks_center() {
+        assert_eq!(8, knight_attacks(SQ_D4).popcount());
+    }
+
+    #[test]
+    fn test_king_attacks_corner() {
+        let attacks = king_attacks(SQ_A1);
+        assert_eq!(
+            Bitboard::from_square(SQ_A2) | Bitboard::from_square(SQ_B2) | Bitboard::from_square(SQ_B1),
+            attacks
+        );
+    }
+
+    #[test]
+    fn test_king_attacks_center() {
+        assert_eq!(8, king_attacks(SQ_D4).popcount());
+    }
+
+    #[test]
+    fn test_knight_attacks_from_matches_generator() {
+        for s in (SQ_A1.0)..(SQ_H8.0 + 1) {
+            assert_eq!(knight_attacks(Square(s)), knight_attacks_from(Square(s)));
+        }
+    }
+
+    #[test]
+    fn test_king_attacks_from_matches_generator() {
+        for s in (SQ_A1.0)..(SQ_H8.0 + 1) {
+            assert_eq!(king_attacks(Square(s)), king_attacks_from(Square(s)));
+        }
+    }
+
+    #[test]
+    fn test_pawn_pushes_double_from_start_rank() {
+        let pushes = pawn_pushes(WHITE, SQ_E2, Bitboard(0));
+        assert_eq!(Bitboard::from_square(SQ_E3) | Bitboard::from_square(SQ_E4), pushes);
+
+        let pushes = pawn_pushes(BLACK, SQ_E7, Bitboard(0));
+        assert_eq!(Bitboard::from_square(SQ_E6) | Bitboard::from_square(SQ_E5), pushes);
+    }
+
+    #[test]
+    fn test_pawn_pushes_blocked_intermediate_square_stops_double_push() {
+        let occupied = Bitboard::from_square(SQ_E3);
+        assert_eq!(Bitboard(0), pawn_pushes(WHITE, SQ_E2, occupied));
+    }
+
+    #[test]
+    fn test_pawn_pushes_blocked_target_square_stops_double_push() {
+        let occupied = Bitboard::from_square(SQ_E4);
+        assert_eq!(Bitboard::from_square(SQ_E3), pawn_pushes(WHITE, SQ_E2, occupied));
+    }
+
+    #[test]
+    fn test_pawn_pushes_single_from_non_start_rank() {
+        assert_eq!(Bitboard::from_square(SQ_E4), pawn_pushes(WHITE, SQ_E3, Bitboard(0)));
+    }
+
+    #[test]
+    fn test_pawn_pushes_illegal_rank_is_empty() {
+        assert_eq!(Bitboard(0), pawn_pushes(WHITE, SQ_E8, Bitboard(0)));
+        assert_eq!(Bitboard(0), pawn_pushes(BLACK, SQ_E1, Bitboard(0)));
+    }
+
+    #[test]
+    fn test_pawn_captures_center() {
+        assert_eq!(
+            Bitboard::from_square(SQ_D3) | Bitboard::from_square(SQ_F3),
+            pawn_captures(WHITE, SQ_E2)
+        );
+    }
+
+    #[test]
+    fn test_pawn_captures_edge_file_does_not_wrap() {
+        assert_eq!(Bitboard::from_square(SQ_B3), pawn_captures(WHITE, SQ_A2));
+        assert_eq!(Bitboard::from_square(SQ_G3), pawn_captures(WHITE, SQ_H2));
+    }
+
+    #[test]
+    fn test_pawn_captures_illegal_rank_is_empty() {
+        assert_eq!(Bitboard(0), pawn_captures(WHITE, SQ_E8));
+        assert_eq!(Bitboard(0), pawn_captures(BLACK, SQ_E1));
+    }
+
+    #[test]
+    fn test_castling_target() {
+        assert_eq!(Bitboard(0), castling_target(WHITE, CastleRights::NoSide));
+        assert_eq!(Bitboard::from_square(SQ_G1), castling_target(WHITE, CastleRights::KingSide));
+        assert_eq!(Bitboard::from_square(SQ_C1), castling_target(WHITE, CastleRights::QueenSide));
+        assert_eq!(
+            Bitboard::from_square(SQ_G1) | Bitboard::from_square(SQ_C1),
+            castling_target(WHITE, CastleRights::BothSides)
+        );
+
+        assert_eq!(Bitboard::from_square(SQ_G8), castling_target(BLACK, CastleRights::KingSide));
+        assert_eq!(Bitboard::from_square(SQ_C8), castling_target(BLACK, CastleRights::QueenSide));
+    }
+
+    #[test]
+    fn test_is_empty() {
+        assert!(Bitboard(0).is_empty());
+        assert!(!Bitboard(1).is_empty());
+    }
+
+    #[test]
+    fn test_popcount_counts_set_bits() {
+        assert_eq!(0, Bitboard(0).popcount());
+        assert_eq!(1, Bitboard(1).popcount());
+        assert_eq!(64, Bitboard(!0u64).popcount());
+        assert_eq!(4, Bitboard(0b1011_0100).popcount());
+    }
+
+    #[test]
+    fn test_lsb_msb_single_square() {
+        assert_eq!(SQ_A1, Bitboard::from_square(SQ_A1).lsb());
+        assert_eq!(SQ_A1, Bitboard::from_square(SQ_A1).msb());
+        assert_eq!(SQ_H8, Bitboard::from_square(SQ_H8).lsb());
+        assert_eq!(SQ_H8, Bitboard::from_square(SQ_H8).msb());
+    }
+
+    #[test]
+    fn test_lsb_msb_pick_opposite_ends() {
+        let both = Bitboard::from_square(SQ_B2) | Bitboard::from_square(SQ_G7);
+        assert_eq!(SQ_B2, both.lsb());
+        assert_eq!(SQ_G7, both.msb());
+    }
+
+    #[test]
+    fn test_msb_index_matches_hardware_msb() {
+        for &sq in &[SQ_A1, SQ_D4, SQ_H8, SQ_A8, SQ_H1] {
+            let bb = Bitboard::from_square(sq);
+            assert_eq!(Square(63 - bb.0.leading_zeros() as i8), msb_index(bb));
+        }
+    }
+
+    #[test]
+    fn test_pext_dispatch_matches_software_pext() {
+        let mask = 0b1011_0100u64;
+        for b in 0u64..16 {
+            assert_eq!(pext(b, mask), pext_dispatch(b, mask));
+        }
+    }
+
+    #[test]
+    fn test_distance() {
+        assert_eq!(0, distance(SQ_E4, SQ_E4));
+        assert_eq!(1, distance(SQ_A1, SQ_B1));
+        assert_eq!(1, distance(SQ_A1, SQ_A2));
+        assert_eq!(7, distance(SQ_A1, SQ_H8));
+        assert_eq!(4, distance(SQ_A1, SQ_E5));
+    }
+
+    #[test]
+    fn test_distance_ring_bb() {
+        // A corner square's first ring is the three squares it's
+        // adjacent to -- no ring wraps off the board.
+        assert_eq!(
+            Bitboard::from_square(SQ_A2) | Bitboard::from_square(SQ_B1) | Bitboard::from_square(SQ_B2),
+            distance_ring(SQ_A1, 1)
+        );
+        assert_eq!(FILE_HBB | RANK_8BB, distance_ring(SQ_A1, 7));
+    }
+
+    #[test]
+    fn test_between_bb() {
+        assert_eq!(
+            Bitboard::from_square(SQ_B1) | Bitboard::from_square(SQ_C1),
+            between(SQ_A1, SQ_D1)
+        );
+        assert_eq!(
+            Bitboard::from_square(SQ_B2) | Bitboard::from_square(SQ_C3)
+                | Bitboard::from_square(SQ_D4) | Bitboard::from_square(SQ_E5)
+                | Bitboard::from_square(SQ_F6) | Bitboard::from_square(SQ_G7),
+            between(SQ_A1, SQ_H8)
+        );
+        // Not aligned on a rank, file, or diagonal -> empty.
+        assert_eq!(Bitboard(0), between(SQ_A1, SQ_B3));
+        assert_eq!(Bitboard(0), between(SQ_A1, SQ_A1));
+    }
+
+    #[test]
+    fn test_line_bb() {
+        assert_eq!(FILE_ABB, line(SQ_A1, SQ_A4));
+        assert_eq!(RANK_1BB, line(SQ_A1, SQ_H1));
+
+        let mut diagonal = Bitboard(0);
+        for &sq in &[SQ_A1, SQ_B2, SQ_C3, SQ_D4, SQ_E5, SQ_F6, SQ_G7, SQ_H8] {
+            diagonal |= sq;
+        }
+        assert_eq!(diagonal, line(SQ_C3, SQ_F6));
+
+        assert_eq!(Bitboard(0), line(SQ_A1, SQ_B3));
+    }
 }
@@ -0,0 +1,215 @@
+// This file is part of the samson library.
+//
+// Copyright (C) 2017 Lakin Wecker <lakin@wecker.ca>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+///-------------------------------------------------------------------------------------------------
+/// A multiplier-based alternative to the parent module's PEXT tables:
+/// `occupied & mask`, multiplied by a per-square "magic" constant and
+/// shifted down, lands on a distinct index for every occupancy subset
+/// that actually changes the attack set -- no BMI2 required. The magics
+/// themselves aren't derived from any closed-form formula; they're found
+/// by trying random multipliers against the known occupancy/attack pairs
+/// until one happens not to collide.
+///-------------------------------------------------------------------------------------------------
+
+use super::super::types::*;
+use super::{file_bb, rank_bb, sliding_attack, FILE_ABB, FILE_HBB, RANK_1BB, RANK_8BB};
+
+/// A square's magic index: `((occupied & mask).wrapping_mul(magic) >>
+/// shift)` selects the slot in `attacks` holding that occupancy's attack
+/// set.
+pub struct MagicEntry {
+    pub mask: Bitboard,
+    pub magic: u64,
+    pub shift: u32,
+    pub attacks: Vec<Bitboard>,
+}
+
+impl MagicEntry {
+    fn index(&self, occupied: Bitboard) -> usize {
+        (((occupied.0 & self.mask.0).wrapping_mul(self.magic)) >> self.shift) as usize
+    }
+}
+
+/// Stockfish's `xorshift64star`: small, seeded, and good enough to search
+/// for magics with -- no crate in this tree provides a PRNG.
+struct Prng {
+    state: u64,
+}
+
+impl Prng {
+    fn new(seed: u64) -> Prng {
+        Prng { state: seed }
+    }
+
+    fn rand64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545F4914F6CDD1Du64)
+    }
+
+    /// A candidate magic with relatively few set bits. Magics found this
+    /// way tend to spread relevant bits into the high end of the product
+    /// faster than a uniformly random 64-bit value would.
+    fn sparse_rand64(&mut self) -> u64 {
+        self.rand64() & self.rand64() & self.rand64()
+    }
+}
+
+/// Every occupancy subset of `mask`, via the carry-rippler trick
+/// (`next = (cur - mask) & mask`, which enumerates every subset exactly
+/// once and returns to the empty set last).
+fn occupancy_subsets(mask: Bitboard) -> Vec<Bitboard> {
+    let mut subsets = Vec::with_capacity(1usize << mask.0.count_ones());
+    let mut subset = Bitboard(0);
+    loop {
+        subsets.push(subset);
+        subset = Bitboard(subset.0.wrapping_sub(mask.0) & mask.0);
+        if subset == Bitboard(0) {
+            break;
+        }
+    }
+    subsets
+}
+
+/// Search for a magic multiplier that indexes `occupancies` onto
+/// `references` (the ground-truth attack set for each occupancy, from
+/// `sliding_attack`) without collisions, trying candidates from `rng`
+/// until one works.
+fn find_magic(mask: Bitboard, shift: u32, occupancies: &[Bitboard], references: &[Bitboard], rng: &mut Prng) -> (u64, Vec<Bitboard>) {
+    let size = occupancies.len();
+    let mut attacks = vec![Bitboard(0); size];
+    let mut seen = vec![false; size];
+    loop {
+        let magic = rng.sparse_rand64();
+        // A cheap pre-filter: a useful magic spreads the mask's relevant
+        // bits widely across the top byte of the product.
+        if (mask.0.wrapping_mul(magic) >> 56).count_ones() < 6 {
+            continue;
+        }
+
+        for flag in seen.iter_mut() {
+            *flag = false;
+        }
+
+        let mut collided = false;
+        for i in 0..size {
+            let idx = ((occupancies[i].0.wrapping_mul(magic)) >> shift) as usize;
+            if seen[idx] && attacks[idx] != references[i] {
+                collided = true;
+                break;
+            }
+            seen[idx] = true;
+            attacks[idx] = references[i];
+        }
+
+        if !collided {
+            return (magic, attacks);
+        }
+    }
+}
+
+/// Build the 64-square magic table for `pt` (`ROOK` or `BISHOP`). `seed`
+/// only affects how quickly the search converges, not the correctness of
+/// the result.
+fn init_magic_table(pt: PieceType, seed: u64) -> Vec<MagicEntry> {
+    let mut rng = Prng::new(seed);
+    let mut table = Vec::with_capacity(SQUARE_NB_USIZE);
+    for sq in 0..SQUARE_NB_USIZE {
+        let square = Square(sq as i8);
+        let edges = ((RANK_1BB | RANK_8BB) & !rank_bb(square))
+            | ((FILE_ABB | FILE_HBB) & !file_bb(square));
+        let mask = sliding_attack(pt, square, Bitboard(0)) & !edges;
+        let shift = 64 - mask.0.count_ones();
+
+        let occupancies = occupancy_subsets(mask);
+        let references: Vec<Bitboard> = occupancies.iter()
+            .map(|&occupied| sliding_attack(pt, square, occupied))
+            .collect();
+
+        let (magic, attacks) = find_magic(mask, shift, &occupancies, &references, &mut rng);
+        table.push(MagicEntry { mask: mask, magic: magic, shift: shift, attacks: attacks });
+    }
+    table
+}
+
+lazy_static! {
+    pub static ref ROOK_MAGICS: Vec<MagicEntry> = init_magic_table(ROOK, 0x1234_5678_9ABC_DEF0u64);
+    pub static ref BISHOP_MAGICS: Vec<MagicEntry> = init_magic_table(BISHOP, 0x0FED_CBA9_8765_4321u64);
+}
+
+/// Rook attacks from `sq` against `occupied`, via the magic table.
+pub fn rook_attacks(sq: Square, occupied: Bitboard) -> Bitboard {
+    let entry = &ROOK_MAGICS[sq.0 as usize];
+    entry.attacks[entry.index(occupied)]
+}
+
+/// Bishop attacks from `sq` against `occupied`, via the magic table.
+pub fn bishop_attacks(sq: Square, occupied: Bitboard) -> Bitboard {
+    let entry = &BISHOP_MAGICS[sq.0 as usize];
+    entry.attacks[entry.index(occupied)]
+}
+
+/// Queen attacks from `sq` against `occupied`: the union of the rook and
+/// bishop attack sets.
+pub fn queen_attacks(sq: Square, occupied: Bitboard) -> Bitboard {
+    rook_attacks(sq, occupied) | bishop_attacks(sq, occupied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::types::*;
+    use super::super::attacks_bb;
+
+    #[test]
+    fn test_rook_attacks_matches_pext_table_open_board() {
+        for s in (SQ_A1.0)..(SQ_H8.0 + 1) {
+            let sq = Square(s);
+            assert_eq!(attacks_bb(ROOK, sq, Bitboard(0)), rook_attacks(sq, Bitboard(0)));
+        }
+    }
+
+    #[test]
+    fn test_bishop_attacks_matches_pext_table_open_board() {
+        for s in (SQ_A1.0)..(SQ_H8.0 + 1) {
+            let sq = Square(s);
+            assert_eq!(attacks_bb(BISHOP, sq, Bitboard(0)), bishop_attacks(sq, Bitboard(0)));
+        }
+    }
+
+    #[test]
+    fn test_rook_attacks_blocked_by_occupancy() {
+        let occupied = Bitboard::from_square(SQ_D4) | Bitboard::from_square(SQ_D6);
+        assert_eq!(attacks_bb(ROOK, SQ_D1, occupied), rook_attacks(SQ_D1, occupied));
+    }
+
+    #[test]
+    fn test_bishop_attacks_blocked_by_occupancy() {
+        let occupied = Bitboard::from_square(SQ_F6);
+        assert_eq!(attacks_bb(BISHOP, SQ_A1, occupied), bishop_attacks(SQ_A1, occupied));
+    }
+
+    #[test]
+    fn test_queen_attacks_is_rook_union_bishop() {
+        let occupied = Bitboard::from_square(SQ_D4);
+        assert_eq!(
+            rook_attacks(SQ_D1, occupied) | bishop_attacks(SQ_D1, occupied),
+            queen_attacks(SQ_D1, occupied)
+        );
+    }
+}
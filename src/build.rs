@@ -152,4 +152,243 @@ fn main() {
     }
     file_mask.build(&mut file).unwrap();
 
+    // Precomputed leaper attack tables, so the runtime never has to
+    // recompute them. For each square we shift by the leaper's legal
+    // offsets and drop anything that would wrap around a file edge.
+    let knight_offsets: [(i8, i8); 8] = [
+        (1, 2), (2, 1), (2, -1), (1, -2), (-1, -2), (-2, -1), (-2, 1), (-1, 2)
+    ];
+    let king_offsets: [(i8, i8); 8] = [
+        (1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1), (0, -1), (1, -1)
+    ];
+
+    let mut bb_knight_attacks = [BB_VOID; 64];
+    let mut bb_king_attacks = [BB_VOID; 64];
+    let mut bb_white_pawn_pushes = [BB_VOID; 64];
+    let mut bb_black_pawn_pushes = [BB_VOID; 64];
+    let mut bb_white_pawn_attacks = [BB_VOID; 64];
+    let mut bb_black_pawn_attacks = [BB_VOID; 64];
+
+    for i in 0..64u8 {
+        let f = file_index(i) as i8;
+        let r = rank_index(i) as i8;
+
+        for &(df, dr) in knight_offsets.iter() {
+            let (nf, nr) = (f + df, r + dr);
+            if nf >= 0 && nf < 8 && nr >= 0 && nr < 8 {
+                bb_knight_attacks[i as usize] |= 1u64 << square(nf as u8, nr as u8);
+            }
+        }
+        for &(df, dr) in king_offsets.iter() {
+            let (nf, nr) = (f + df, r + dr);
+            if nf >= 0 && nf < 8 && nr >= 0 && nr < 8 {
+                bb_king_attacks[i as usize] |= 1u64 << square(nf as u8, nr as u8);
+            }
+        }
+
+        if r < 7 {
+            bb_white_pawn_pushes[i as usize] |= 1u64 << square(f as u8, (r + 1) as u8);
+        }
+        if r > 0 {
+            bb_black_pawn_pushes[i as usize] |= 1u64 << square(f as u8, (r - 1) as u8);
+        }
+        for &df in [-1i8, 1i8].iter() {
+            let nf = f + df;
+            if nf >= 0 && nf < 8 {
+                if r < 7 {
+                    bb_white_pawn_attacks[i as usize] |= 1u64 << square(nf as u8, (r + 1) as u8);
+                }
+                if r > 0 {
+                    bb_black_pawn_attacks[i as usize] |= 1u64 << square(nf as u8, (r - 1) as u8);
+                }
+            }
+        }
+    }
+
+    fn write_bb_table(file: &mut BufWriter<File>, name: &str, table: &[u64; 64]) {
+        write!(file, "const {}: [u64; 64] = [", name).unwrap();
+        for (i, v) in table.iter().enumerate() {
+            if i > 0 { write!(file, ", ").unwrap(); }
+            write!(file, "0b{:064b}", v).unwrap();
+        }
+        write!(file, "];\n").unwrap();
+    }
+
+    write_bb_table(&mut file, "BB_KNIGHT_ATTACKS", &bb_knight_attacks);
+    write_bb_table(&mut file, "BB_KING_ATTACKS", &bb_king_attacks);
+    write_bb_table(&mut file, "BB_WHITE_PAWN_PUSHES", &bb_white_pawn_pushes);
+    write_bb_table(&mut file, "BB_BLACK_PAWN_PUSHES", &bb_black_pawn_pushes);
+    write_bb_table(&mut file, "BB_WHITE_PAWN_ATTACKS", &bb_white_pawn_attacks);
+    write_bb_table(&mut file, "BB_BLACK_PAWN_ATTACKS", &bb_black_pawn_attacks);
+
+    // Rook/bishop magic bitboards, precomputed here rather than searched
+    // for at runtime (`bitboard::magic` re-derives its own copy lazily;
+    // this is the build-time equivalent, flattened into plain arrays so
+    // the generated tables need no search at all). Same algorithm as
+    // `bitboard::magic`: mask out the board edges (a slider's ray simply
+    // ends there regardless of occupancy), enumerate every relevant-
+    // occupancy subset via the carry-rippler trick, then search random
+    // sparse multipliers until one indexes every subset without collision.
+    const ROOK_DELTAS: [(i8, i8); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+    const BISHOP_DELTAS: [(i8, i8); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+
+    fn sliding_attack(deltas: &[(i8, i8); 4], sq: u8, occupied: u64) -> u64 {
+        let start_file = file_index(sq) as i8;
+        let start_rank = rank_index(sq) as i8;
+        let mut attack = 0u64;
+        for &(df, dr) in deltas.iter() {
+            let mut f = start_file + df;
+            let mut r = start_rank + dr;
+            while f >= 0 && f < 8 && r >= 0 && r < 8 {
+                let bit = 1u64 << square(f as u8, r as u8);
+                attack |= bit;
+                if occupied & bit != 0 {
+                    break;
+                }
+                f += df;
+                r += dr;
+            }
+        }
+        attack
+    }
+
+    fn occupancy_subsets(mask: u64) -> Vec<u64> {
+        let mut subsets = Vec::with_capacity(1usize << mask.count_ones());
+        let mut subset = 0u64;
+        loop {
+            subsets.push(subset);
+            subset = subset.wrapping_sub(mask) & mask;
+            if subset == 0 {
+                break;
+            }
+        }
+        subsets
+    }
+
+    struct MagicPrng {
+        state: u64,
+    }
+
+    impl MagicPrng {
+        fn new(seed: u64) -> MagicPrng {
+            MagicPrng { state: seed }
+        }
+
+        fn rand64(&mut self) -> u64 {
+            self.state ^= self.state >> 12;
+            self.state ^= self.state << 25;
+            self.state ^= self.state >> 27;
+            self.state.wrapping_mul(0x2545F4914F6CDD1Du64)
+        }
+
+        fn sparse_rand64(&mut self) -> u64 {
+            self.rand64() & self.rand64() & self.rand64()
+        }
+    }
+
+    fn find_magic(mask: u64, shift: u32, occupancies: &[u64], references: &[u64], rng: &mut MagicPrng) -> (u64, Vec<u64>) {
+        let size = occupancies.len();
+        let mut attacks = vec![0u64; size];
+        let mut seen = vec![false; size];
+        loop {
+            let magic = rng.sparse_rand64();
+            if (mask.wrapping_mul(magic) >> 56).count_ones() < 6 {
+                continue;
+            }
+
+            for flag in seen.iter_mut() {
+                *flag = false;
+            }
+
+            let mut collided = false;
+            for i in 0..size {
+                let idx = (occupancies[i].wrapping_mul(magic) >> shift) as usize;
+                if seen[idx] && attacks[idx] != references[i] {
+                    collided = true;
+                    break;
+                }
+                seen[idx] = true;
+                attacks[idx] = references[i];
+            }
+
+            if !collided {
+                return (magic, attacks);
+            }
+        }
+    }
+
+    fn write_magic_tables(
+        file: &mut BufWriter<File>,
+        prefix: &str,
+        deltas: &'static [(i8, i8); 4],
+        seed: u64,
+    ) {
+        let mut rng = MagicPrng::new(seed);
+        let mut masks = [0u64; 64];
+        let mut magics = [0u64; 64];
+        let mut shifts = [0u32; 64];
+        let mut offsets = [0usize; 64];
+        let mut flattened: Vec<u64> = Vec::new();
+
+        for sq in 0..64u8 {
+            let f = file_index(sq);
+            let r = rank_index(sq);
+            let rank_edges: u64 = 0xFF000000000000FFu64 & !(0xFFu64 << (8 * r as u32));
+            let file_edges: u64 = 0x8181818181818181u64 & !(0x0101010101010101u64 << (f as u32));
+            let edges = rank_edges | file_edges;
+            let mask = sliding_attack(deltas, sq, 0) & !edges;
+            let shift = 64 - mask.count_ones();
+
+            let occupancies = occupancy_subsets(mask);
+            let references: Vec<u64> = occupancies.iter()
+                .map(|&occupied| sliding_attack(deltas, sq, occupied))
+                .collect();
+
+            let (magic, attacks) = find_magic(mask, shift, &occupancies, &references, &mut rng);
+
+            masks[sq as usize] = mask;
+            magics[sq as usize] = magic;
+            shifts[sq as usize] = shift;
+            offsets[sq as usize] = flattened.len();
+            flattened.extend(attacks);
+        }
+
+        write!(file, "const {}_MASKS: [u64; 64] = [", prefix).unwrap();
+        for (i, v) in masks.iter().enumerate() {
+            if i > 0 { write!(file, ", ").unwrap(); }
+            write!(file, "0b{:064b}", v).unwrap();
+        }
+        write!(file, "];\n").unwrap();
+
+        write!(file, "const {}_MAGICS: [u64; 64] = [", prefix).unwrap();
+        for (i, v) in magics.iter().enumerate() {
+            if i > 0 { write!(file, ", ").unwrap(); }
+            write!(file, "0b{:064b}", v).unwrap();
+        }
+        write!(file, "];\n").unwrap();
+
+        write!(file, "const {}_SHIFTS: [u32; 64] = [", prefix).unwrap();
+        for (i, v) in shifts.iter().enumerate() {
+            if i > 0 { write!(file, ", ").unwrap(); }
+            write!(file, "{}", v).unwrap();
+        }
+        write!(file, "];\n").unwrap();
+
+        write!(file, "const {}_OFFSETS: [usize; 64] = [", prefix).unwrap();
+        for (i, v) in offsets.iter().enumerate() {
+            if i > 0 { write!(file, ", ").unwrap(); }
+            write!(file, "{}", v).unwrap();
+        }
+        write!(file, "];\n").unwrap();
+
+        write!(file, "const {}_ATTACKS: [u64; {}] = [", prefix, flattened.len()).unwrap();
+        for (i, v) in flattened.iter().enumerate() {
+            if i > 0 { write!(file, ", ").unwrap(); }
+            write!(file, "0b{:064b}", v).unwrap();
+        }
+        write!(file, "];\n").unwrap();
+    }
+
+    write_magic_tables(&mut file, "BB_ROOK", &ROOK_DELTAS, 0x1234_5678_9ABC_DEF0u64);
+    write_magic_tables(&mut file, "BB_BISHOP", &BISHOP_DELTAS, 0x0FED_CBA9_8765_4321u64);
 }
@@ -0,0 +1,148 @@
+// samson - An engine focused on teaching humans.
+//
+// Copyright (C) 2017 Lakin Wecker <lakin@wecker.ca>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+// Incremental (NNUE-style) evaluation accumulator.
+//
+// The first layer of the network sums one weight column per active
+// "feature" (a king-relative piece/square pair, from the perspective of
+// each side). Rather than recompute this sum from scratch at every node,
+// `StateInfo` carries an `Accumulator` that is updated incrementally as
+// pieces move: `DirtyPiece` records which (piece, from, to) triples
+// changed this move, and `update_accumulator` replays them against the
+// nearest ancestor whose accumulator is already computed.
+
+use types::*;
+
+///-----------------------------------------------------------------------------
+/// Number of outputs in the (single, toy) first layer.
+pub const ACCUMULATOR_SIZE: usize = 256;
+
+/// Number of king buckets times piece/square combinations. This is a
+/// deliberately small stand-in for a real HalfKP-style feature set.
+/// `feature_index` packs `(king_bucket * PIECE_NB_USIZE + piece) *
+/// SQUARE_NB_USIZE + relative_sq`, so this needs a king-bucket factor on
+/// top of the piece/square space, not just the piece/square space alone.
+pub const FEATURE_NB: usize = SQUARE_NB_USIZE * PIECE_NB_USIZE * SQUARE_NB_USIZE;
+
+///-----------------------------------------------------------------------------
+/// Up to three (piece, from, to) triples changed by a single move: the
+/// moving piece, plus a captured piece and/or a castling rook.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DirtyPiece {
+    pub dirty_num: i32,
+    pub pc: [Piece; 3],
+    pub from: [Square; 3],
+    pub to: [Square; 3],
+}
+
+pub const DIRTY_PIECE_NONE: DirtyPiece = DirtyPiece {
+    dirty_num: 0,
+    pc: [NO_PIECE; 3],
+    from: [SQ_NONE; 3],
+    to: [SQ_NONE; 3],
+};
+
+///-----------------------------------------------------------------------------
+/// Per-perspective running sums of first-layer feature weights, plus a
+/// flag recording whether they are up to date for that perspective.
+#[derive(Clone, Copy)]
+pub struct Accumulator {
+    pub computed: [bool; COLOR_NB_USIZE],
+    pub accumulation: [[i16; ACCUMULATOR_SIZE]; COLOR_NB_USIZE],
+}
+
+pub const ACCUMULATOR_EMPTY: Accumulator = Accumulator {
+    computed: [false; COLOR_NB_USIZE],
+    accumulation: [[0i16; ACCUMULATOR_SIZE]; COLOR_NB_USIZE],
+};
+
+///-----------------------------------------------------------------------------
+/// The (king-bucket, piece, square) feature index used from `perspective`'s
+/// point of view. King moves change this mapping for every other piece,
+/// which is why a king move forces a full refresh rather than an
+/// incremental update.
+pub fn feature_index(perspective: Color, king_square: Square, pc: Piece, sq: Square) -> usize {
+    let king_bucket = relative_square(perspective, king_square).0 as usize;
+    let piece_index = pc.0 as usize;
+    let relative_sq = relative_square(perspective, sq).0 as usize;
+    (king_bucket * PIECE_NB_USIZE + piece_index) * SQUARE_NB_USIZE + relative_sq
+}
+
+///-----------------------------------------------------------------------------
+pub struct FeatureWeights {
+    pub weights: [[i16; ACCUMULATOR_SIZE]; FEATURE_NB],
+}
+
+impl FeatureWeights {
+    fn add_feature(&self, acc: &mut [i16; ACCUMULATOR_SIZE], feature: usize) {
+        for i in 0..ACCUMULATOR_SIZE {
+            acc[i] = acc[i].wrapping_add(self.weights[feature][i]);
+        }
+    }
+    fn remove_feature(&self, acc: &mut [i16; ACCUMULATOR_SIZE], feature: usize) {
+        for i in 0..ACCUMULATOR_SIZE {
+            acc[i] = acc[i].wrapping_sub(self.weights[feature][i]);
+        }
+    }
+}
+
+///-----------------------------------------------------------------------------
+/// Fully recompute `acc.accumulation[perspective]` from the `(piece,
+/// square)` pairs in `pieces`, indexed by `king_square`. Used on refresh
+/// (king moves, or when there is no computed ancestor to replay forward
+/// from).
+pub fn refresh_accumulator(
+    weights: &FeatureWeights,
+    acc: &mut Accumulator,
+    perspective: Color,
+    king_square: Square,
+    pieces: &[(Piece, Square)],
+) {
+    let mut sum = [0i16; ACCUMULATOR_SIZE];
+    for &(pc, sq) in pieces {
+        weights.add_feature(&mut sum, feature_index(perspective, king_square, pc, sq));
+    }
+    acc.accumulation[perspective.0 as usize] = sum;
+    acc.computed[perspective.0 as usize] = true;
+}
+
+///-----------------------------------------------------------------------------
+/// Replay a single `DirtyPiece` forward on top of an already-computed
+/// accumulator: subtract the moved-from features and add the moved-to
+/// features. Callers are responsible for walking back through
+/// `StateInfo::previous` to find the nearest computed ancestor and for
+/// calling `refresh_accumulator` instead whenever the king itself moved.
+pub fn update_accumulator(
+    weights: &FeatureWeights,
+    acc: &mut Accumulator,
+    perspective: Color,
+    king_square: Square,
+    dirty: &DirtyPiece,
+) {
+    let mut sum = acc.accumulation[perspective.0 as usize];
+    for i in 0..(dirty.dirty_num as usize) {
+        let pc = dirty.pc[i];
+        if dirty.from[i] != SQ_NONE {
+            weights.remove_feature(&mut sum, feature_index(perspective, king_square, pc, dirty.from[i]));
+        }
+        if dirty.to[i] != SQ_NONE {
+            weights.add_feature(&mut sum, feature_index(perspective, king_square, pc, dirty.to[i]));
+        }
+    }
+    acc.accumulation[perspective.0 as usize] = sum;
+    acc.computed[perspective.0 as usize] = true;
+}
@@ -30,4 +30,9 @@ extern crate nom;
 
 pub mod types;
 pub mod parser;
+pub mod position;
+pub mod eval;
+pub mod material;
+pub mod bitboard;
+pub mod zobrist;
 
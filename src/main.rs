@@ -10,8 +10,6 @@ use std::str;
 
 use samson::parser::pgn::*;
 use samson::parser::san::*;
-use nom::IResult::*;
-use nom::Slice;
 
 use encoding::{Encoding, DecoderTrap};
 use encoding::all::{ISO_8859_1, UTF_8};
@@ -46,18 +44,5 @@ fn main() {
     }
     let bytes = decoded.as_bytes();
     let games = pgn(bytes);
-    match games {
-        Done(left, games) => {
-            println!("Read {:?} games.", games.len());
-            println!("Left {:?} bytes.", left.len());
-        },
-        Error(e) =>  {
-            println!("Error!: {:?}", e);
-            println!("Error reading games");
-        }
-        Incomplete(_) => {
-            println!("Incomplete!");
-            println!("Error reading games");
-        }
-    }
+    println!("Read {:?} games.", games.len());
 }
@@ -0,0 +1,121 @@
+// samson - An engine focused on teaching humans.
+//
+// Copyright (C) 2004-2008 Tord Romstad (Glaurung author)
+// Copyright (C) 2008-2015 Marco Costalba, Joona Kiiski, Tord Romstad (Stockfish Authors)
+// Copyright (C) 2015-2017 Marco Costalba, Joona Kiiski, Gary Linscott, Tord Romstad (Stockfish Authors)
+// Copyright (C) 2017 Lakin Wecker
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+// Non-linear material imbalance, following Stockfish's material.cpp: a
+// piece's value isn't just its own constant -- two rooks are worth less
+// than twice one rook, and knights gain value as pawns pile up. Rather
+// than sum flat piece values, each own piece type i contributes
+// `own[i] * (linear[i] + sum_{j<=i} quadratic_ours[i][j]*own[j] +
+// quadratic_theirs[i][j]*their[j])`, with the bishop pair folded in as
+// an extra pseudo piece-type at index 0.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use types::*;
+
+/// `own`/`their` piece-count rows are indexed this way: the bishop pair
+/// (0 or 1) first, then the piece types that can actually imbalance.
+pub const BISHOP_PAIR: usize = 0;
+pub const PIECE_KINDS: usize = 6;
+
+pub fn piece_kind_index(pt: PieceType) -> usize {
+    match pt {
+        PAWN => 1,
+        KNIGHT => 2,
+        BISHOP => 3,
+        ROOK => 4,
+        QUEEN => 5,
+        _ => BISHOP_PAIR,
+    }
+}
+
+/// Constant term for each piece kind. Tunable; these are placeholder
+/// magnitudes in centipawns, not the result of any real tuning run.
+pub const LINEAR: [i32; PIECE_KINDS] = [
+    1438, // bishop pair
+    -162, // pawn
+    -1122, // knight
+    -183, // bishop
+    249, // rook
+    -3532, // queen
+];
+
+/// `quadratic_ours[i][j]` (j <= i): bonus per own-piece-`i`/own-piece-`j`
+/// pair. Only the lower triangle (j <= i) is used; Stockfish mirrors the
+/// upper triangle but we never read it, so it's left zeroed.
+pub const QUADRATIC_OURS: [[i32; PIECE_KINDS]; PIECE_KINDS] = [
+    //            bishop pair    P       N       B       R       Q
+    /* bishop */ [   0,          0,      0,      0,      0,      0 ],
+    /* pawn   */ [   2,          2,      0,      0,      0,      0 ],
+    /* knight */ [   7,          7,     -3,      0,      0,      0 ],
+    /* bishop */ [   8,          1,      1,     -2,      0,      0 ],
+    /* rook   */ [  -2,          5,      3,      3,      0,      0 ],
+    /* queen  */ [-17,          -4,     -2,      0,      1,      0 ],
+];
+
+/// `quadratic_theirs[i][j]` (j <= i): bonus per own-piece-`i`/enemy-piece-`j`
+/// pair (redundancy/complementarity across the board).
+pub const QUADRATIC_THEIRS: [[i32; PIECE_KINDS]; PIECE_KINDS] = [
+    //            bishop pair    P       N       B       R       Q
+    /* bishop */ [   0,          0,      0,      0,      0,      0 ],
+    /* pawn   */ [   0,          0,      0,      0,      0,      0 ],
+    /* knight */ [   0,          4,      0,      0,      0,      0 ],
+    /* bishop */ [   0,          1,      1,      0,      0,      0 ],
+    /* rook   */ [   0,         -2,     -3,     -2,      0,      0 ],
+    /* queen  */ [   0,         -2,     -3,     -3,      5,      0 ],
+];
+
+///-----------------------------------------------------------------------------
+/// Compute the imbalance for the side whose piece counts are `own`,
+/// against an opponent whose counts are `their`. Both arrays follow the
+/// `piece_kind_index` layout. No separate endgame term is computed: the
+/// one bonus fills both halves of the `Score`, so `mg_value` and
+/// `eg_value` read back the same number.
+pub fn imbalance(own: &[i32; PIECE_KINDS], their: &[i32; PIECE_KINDS]) -> Score {
+    let mut bonus: i32 = 0;
+    for i in 0..PIECE_KINDS {
+        if own[i] == 0 {
+            continue;
+        }
+        let mut v = LINEAR[i];
+        for j in 0..(i + 1) {
+            v += QUADRATIC_OURS[i][j] * own[j] + QUADRATIC_THEIRS[i][j] * their[j];
+        }
+        bonus += own[i] * v;
+    }
+    make_score(bonus as u16 as u32, bonus as u16 as u32)
+}
+
+///-----------------------------------------------------------------------------
+lazy_static! {
+    static ref MATERIAL_TABLE: Mutex<HashMap<Key, Score>> = Mutex::new(HashMap::new());
+}
+
+/// Look up `key` in the shared material-entry cache, computing and
+/// inserting it via `imbalance` on a miss.
+pub fn probe(key: Key, own: &[i32; PIECE_KINDS], their: &[i32; PIECE_KINDS]) -> Score {
+    let mut table = MATERIAL_TABLE.lock().unwrap();
+    if let Some(&value) = table.get(&key) {
+        return value;
+    }
+    let value = imbalance(own, their);
+    table.insert(key, value);
+    value
+}
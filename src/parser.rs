@@ -22,6 +22,14 @@
 
 use super::types;
 
+pub mod bom;
+pub mod san;
+pub mod pgn;
+pub mod fen;
+pub mod uci;
+pub mod epd;
+pub mod movetext;
+
 //------------------------------------------------------------------------------
 // UCI related parsers
 named!(pub file <types::File>, chain!(
@@ -88,7 +96,7 @@ named!(pub uci <types::Move>, chain!(
         match (from, to) {
             (types::SQUARE_NB, _) | (_, types::SQUARE_NB) => types::MOVE_NULL,
             _ => match promotion {
-                Some(p) => types::make_move_with_promotion(from, to, p),
+                Some(p) => types::make_move_promotion(from, to, p),
                 None => types::make_move_simple(from, to)
             }
         }
@@ -157,7 +165,7 @@ mod tests {
 
     #[test]
     fn test_uci() {
-        assert_eq!(Done(&[][..], types::make_move_with_promotion(types::SQ_E2, types::SQ_E4, types::KNIGHT)), uci(b"e2e4n"));
+        assert_eq!(Done(&[][..], types::make_move_promotion(types::SQ_E2, types::SQ_E4, types::KNIGHT)), uci(b"e2e4n"));
         assert_eq!(Done(&[][..], types::MOVE_NULL), uci(b"0000"));
     }
 }
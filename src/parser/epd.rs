@@ -0,0 +1,204 @@
+// This file is part of the samson library.
+//
+// Copyright (C) 2017 Lakin Wecker <lakin@wecker.ca>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+///-------------------------------------------------------------------------------------------------
+/// Parsers for Extended Position Description (EPD) records: the first four
+/// FEN fields (board, side to move, castling rights, en passant square)
+/// followed by a semicolon-separated list of opcode/operand pairs. This is
+/// the format used by tactical and perft test suites.
+///-------------------------------------------------------------------------------------------------
+
+use super::fen;
+use super::san;
+
+use std::collections::HashMap;
+use std::str;
+use std::str::FromStr;
+
+///-------------------------------------------------------------------------------------------------
+#[derive(Clone, Debug, PartialEq)]
+pub enum Operand<'a> {
+    Moves(Vec<san::Node>),
+    QuotedString(&'a [u8]),
+    Integer(i64),
+    Raw(&'a [u8])
+}
+
+///-------------------------------------------------------------------------------------------------
+#[derive(Clone, Debug, PartialEq)]
+pub struct Epd<'a> {
+    pub placement: Vec<fen::Node>,
+    pub operations: HashMap<String, Operand<'a>>
+}
+
+///-------------------------------------------------------------------------------------------------
+/// The first four FEN fields -- board, side to move, castling rights and the
+/// en passant square -- with no halfmove clock or fullmove number, since EPD
+/// stops there and hands the rest of the line over to opcodes instead.
+named!(pub epd_position<Vec<fen::Node> >,
+    do_parse!(
+        placement: fen::piece_placement >>
+        char!(' ') >>
+        to_move: fen::color_to_move >>
+        char!(' ') >>
+        castling: fen::castling_rights >>
+        char!(' ') >>
+        ep: fen::ep_square >>
+        ({
+            let mut nodes = placement;
+            nodes.push(to_move);
+            nodes.extend(castling);
+            if let Some(sq) = ep {
+                nodes.push(fen::Node::EnPassantTargetSquare(sq));
+            }
+            nodes
+        })
+    )
+);
+
+///-------------------------------------------------------------------------------------------------
+named!(opcode_token<&[u8]>, is_a!("abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789"));
+
+///-------------------------------------------------------------------------------------------------
+named!(operand_raw<&[u8]>, is_not!(";"));
+
+///-------------------------------------------------------------------------------------------------
+named!(san_move_list<Vec<san::Node> >, separated_list!(char!(' '), san::san_move));
+
+///-------------------------------------------------------------------------------------------------
+named!(epd_integer<i64>,
+    map_res!(map_res!(recognize!(pair!(opt!(char!('-')), is_a!("0123456789"))), str::from_utf8), FromStr::from_str)
+);
+
+///-------------------------------------------------------------------------------------------------
+/// Interpret the raw bytes between an opcode and its terminating `;`
+/// according to that opcode's known shape, falling back to `Operand::Raw`
+/// for anything we don't recognize (or that doesn't parse as expected).
+fn parse_operand<'a>(opcode: &[u8], raw: &'a [u8]) -> Operand<'a> {
+    use nom::IResult::Done;
+    match opcode {
+        b"bm" | b"am" => match san_move_list(raw) {
+            Done(_, moves) => Operand::Moves(moves),
+            _ => Operand::Raw(raw)
+        },
+        b"ce" | b"dm" | b"acd" | b"acn" => match epd_integer(raw) {
+            Done(_, n) => Operand::Integer(n),
+            _ => Operand::Raw(raw)
+        },
+        b"id" | b"c0" | b"c1" | b"c2" | b"c3" | b"c4" | b"c5" | b"c6" | b"c7" | b"c8" | b"c9" => {
+            match delimited!(raw, char!('"'), is_not!("\""), char!('"')) {
+                Done(_, s) => Operand::QuotedString(s),
+                _ => Operand::Raw(raw)
+            }
+        },
+        _ => Operand::Raw(raw)
+    }
+}
+
+///-------------------------------------------------------------------------------------------------
+named!(pub operation<(String, Operand)>,
+    do_parse!(
+        opcode: opcode_token >>
+        char!(' ') >>
+        raw: operand_raw >>
+        char!(';') >>
+        ((str::from_utf8(opcode).unwrap().to_string(), parse_operand(opcode, raw)))
+    )
+);
+
+///-------------------------------------------------------------------------------------------------
+named!(pub epd<Epd>,
+    do_parse!(
+        placement: epd_position >>
+        char!(' ') >>
+        ops: many0!(ws!(operation)) >>
+        ({
+            let mut operations = HashMap::new();
+            for (opcode, operand) in ops {
+                operations.insert(opcode, operand);
+            }
+            Epd{ placement: placement, operations: operations }
+        })
+    )
+);
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use super::super::super::types::*;
+    use nom::IResult::*;
+
+    #[test]
+    fn test_epd_position() {
+        let result = epd_position(b"rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -");
+        match result {
+            Done(_, nodes) => {
+                assert!(nodes.contains(&fen::Node::Move(WHITE)));
+                assert!(nodes.contains(&fen::Node::Castle(WHITE, FILE_H)));
+            },
+            _ => assert!(false, "Unable to parse epd_position")
+        }
+    }
+
+    #[test]
+    fn test_operation_bm() {
+        let nxc3 = san::Node::Move(
+            KNIGHT, san::Source::None, san::MoveOrCapture::Move, SQ_C3,
+            san::Promotion::None, san::Check::None, Vec::new()
+        );
+        assert_eq!(
+            Done(&b""[..], ("bm".to_string(), Operand::Moves(vec![nxc3]))),
+            operation(b"bm Nc3;")
+        );
+    }
+
+    #[test]
+    fn test_operation_id() {
+        assert_eq!(
+            Done(&b""[..], ("id".to_string(), Operand::QuotedString(&b"my test case"[..]))),
+            operation(b"id \"my test case\";")
+        );
+    }
+
+    #[test]
+    fn test_operation_ce() {
+        assert_eq!(Done(&b""[..], ("ce".to_string(), Operand::Integer(34))), operation(b"ce 34;"));
+        assert_eq!(Done(&b""[..], ("dm".to_string(), Operand::Integer(-3))), operation(b"dm -3;"));
+    }
+
+    #[test]
+    fn test_epd_record() {
+        let result = epd(
+            b"r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - bm Nc3; id \"test case\";"
+        );
+        match result {
+            Done(_, record) => {
+                assert!(record.placement.contains(&fen::Node::Move(WHITE)));
+                match record.operations.get("id") {
+                    Some(&Operand::QuotedString(s)) => assert_eq!(s, &b"test case"[..]),
+                    _ => assert!(false, "Missing id operation")
+                }
+                match record.operations.get("bm") {
+                    Some(&Operand::Moves(ref moves)) => assert_eq!(moves.len(), 1),
+                    _ => assert!(false, "Missing bm operation")
+                }
+            },
+            _ => assert!(false, "Unable to parse epd record")
+        }
+    }
+}
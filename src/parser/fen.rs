@@ -20,7 +20,15 @@
 //------------------------------------------------------------------------------
 
 use super::super::types::*;
+use super::super::bitboard;
+use super::super::position;
+use super::square;
 
+use std::str;
+use std::str::FromStr;
+
+///-----------------------------------------------------------------------------
+pub const STARTING_FEN: &'static str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 
 ///-----------------------------------------------------------------------------
 #[derive(Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Debug, Hash)]
@@ -86,33 +94,357 @@ named!(pub color_to_move<&[u8], Node >,
 ///-----------------------------------------------------------------------------
 named!(pub castling_rights<&[u8], Vec<Node> >,
     many0!(
-	map!(one_of!("-KQkqABCEDFGHabcdefgh"), |c: char| { match c { 
-	    'k' => Node::Castle(WHITE, FILE_H),
-	    'q' => Node::Castle(WHITE, FILE_A),
-	    'a' => Node::Castle(WHITE, FILE_A),
-	    'b' => Node::Castle(WHITE, FILE_B),
-	    'c' => Node::Castle(WHITE, FILE_C),
-	    'd' => Node::Castle(WHITE, FILE_D),
-	    'e' => Node::Castle(WHITE, FILE_E),
-	    'f' => Node::Castle(WHITE, FILE_F),
-	    'g' => Node::Castle(WHITE, FILE_G),
-	    'h' => Node::Castle(WHITE, FILE_H),
-	    'K' => Node::Castle(BLACK, FILE_H),
-	    'Q' => Node::Castle(BLACK, FILE_A),
-	    'A' => Node::Castle(BLACK, FILE_A),
-	    'B' => Node::Castle(BLACK, FILE_B),
-	    'C' => Node::Castle(BLACK, FILE_C),
-	    'D' => Node::Castle(BLACK, FILE_D),
-	    'E' => Node::Castle(BLACK, FILE_E),
-	    'F' => Node::Castle(BLACK, FILE_F),
-	    'G' => Node::Castle(BLACK, FILE_G),
-	    'H' => Node::Castle(BLACK, FILE_H),
+	map!(one_of!("-KQkqABCEDFGHabcdefgh"), |c: char| { match c {
+	    'K' => Node::Castle(WHITE, FILE_H),
+	    'Q' => Node::Castle(WHITE, FILE_A),
+	    'A' => Node::Castle(WHITE, FILE_A),
+	    'B' => Node::Castle(WHITE, FILE_B),
+	    'C' => Node::Castle(WHITE, FILE_C),
+	    'D' => Node::Castle(WHITE, FILE_D),
+	    'E' => Node::Castle(WHITE, FILE_E),
+	    'F' => Node::Castle(WHITE, FILE_F),
+	    'G' => Node::Castle(WHITE, FILE_G),
+	    'H' => Node::Castle(WHITE, FILE_H),
+	    'k' => Node::Castle(BLACK, FILE_H),
+	    'q' => Node::Castle(BLACK, FILE_A),
+	    'a' => Node::Castle(BLACK, FILE_A),
+	    'b' => Node::Castle(BLACK, FILE_B),
+	    'c' => Node::Castle(BLACK, FILE_C),
+	    'd' => Node::Castle(BLACK, FILE_D),
+	    'e' => Node::Castle(BLACK, FILE_E),
+	    'f' => Node::Castle(BLACK, FILE_F),
+	    'g' => Node::Castle(BLACK, FILE_G),
+	    'h' => Node::Castle(BLACK, FILE_H),
 	    '-' => Node::NoCastling,
 	    _ => Node::Error(c) // This should never happen because of above.
 	}})
     )
 );
 
+///-----------------------------------------------------------------------------
+named!(pub ep_square<Option<Square> >,
+    alt_complete!(
+        map!(char!('-'), |_| None) |
+        map!(square, |sq| { if sq == SQUARE_NB { None } else { Some(sq) } })
+    )
+);
+
+///-----------------------------------------------------------------------------
+named!(fen_integer<u16>, map_res!(map_res!(is_a!("0123456789"), str::from_utf8), FromStr::from_str));
+
+///-----------------------------------------------------------------------------
+/// The full six FEN fields, space-separated, as a flat list of `Node`s in
+/// the order they were read. The halfmove clock and fullmove number are
+/// optional, matching the lenient FEN dialect `Position::set` already
+/// accepts.
+named!(pub fen<Vec<Node> >,
+    do_parse!(
+        placement: piece_placement >>
+        char!(' ') >>
+        to_move: color_to_move >>
+        char!(' ') >>
+        castling: castling_rights >>
+        char!(' ') >>
+        ep: ep_square >>
+        halfmove: opt!(complete!(preceded!(char!(' '), fen_integer))) >>
+        fullmove: opt!(complete!(preceded!(char!(' '), fen_integer))) >>
+        ({
+            let mut nodes = placement;
+            nodes.push(to_move);
+            nodes.extend(castling);
+            if let Some(sq) = ep {
+                nodes.push(Node::EnPassantTargetSquare(sq));
+            }
+            if let Some(h) = halfmove {
+                nodes.push(Node::HalfMoveClock(h));
+            }
+            if let Some(f) = fullmove {
+                nodes.push(Node::FullMoveNumber(f));
+            }
+            nodes
+        })
+    )
+);
+
+///-----------------------------------------------------------------------------
+// Position-legality status flags, mirroring python-chess's `Board.status()`
+// bitmask. `validate` only looks at what a flat `Vec<Node>` already tells
+// us (piece counts, pawn placement, the claimed castling/en-passant
+// fields, and whether the side not to move is in check) -- it doesn't
+// require a full `Position`, so it can run directly against the syntactic
+// parse before anyone decides to build one.
+pub const STATUS_VALID: u32 = 0;
+pub const STATUS_NO_WHITE_KING: u32 = 1;
+pub const STATUS_NO_BLACK_KING: u32 = 2;
+pub const STATUS_TOO_MANY_KINGS: u32 = 4;
+pub const STATUS_TOO_MANY_WHITE_PAWNS: u32 = 8;
+pub const STATUS_TOO_MANY_BLACK_PAWNS: u32 = 16;
+pub const STATUS_PAWNS_ON_BACK_RANK: u32 = 32;
+pub const STATUS_TOO_MANY_WHITE_PIECES: u32 = 64;
+pub const STATUS_TOO_MANY_BLACK_PIECES: u32 = 128;
+pub const STATUS_BAD_CASTLING_RIGHTS: u32 = 256;
+pub const STATUS_INVALID_EP_SQUARE: u32 = 512;
+pub const STATUS_OPPOSITE_CHECK: u32 = 1024;
+
+/// Replay a flat `Vec<Node>` (as produced by `fen`) into an 8x8 board, the
+/// side to move, and which color(s) claimed which-side castling rights.
+fn reconstruct(nodes: &[Node]) -> ([Piece; SQUARE_NB_USIZE], Color, Vec<(Color, File)>) {
+    let mut board = [NO_PIECE; SQUARE_NB_USIZE];
+    let mut file = FILE_A;
+    let mut rank = RANK_8;
+    let mut side_to_move = WHITE;
+    let mut castling = Vec::new();
+    for node in nodes {
+        match *node {
+            Node::Drop(pc) => {
+                if file.0 < FILE_NB.0 && rank.0 >= RANK_1.0 {
+                    board[make_square(file, rank).0 as usize] = pc;
+                }
+                file = File(file.0 + 1);
+            }
+            Node::Skip(n) => { file = File(file.0 + n as i8); }
+            Node::NextRank => { rank = Rank(rank.0 - 1); file = FILE_A; }
+            Node::Move(c) => { side_to_move = c; }
+            Node::Castle(c, f) => castling.push((c, f)),
+            _ => {}
+        }
+    }
+    (board, side_to_move, castling)
+}
+
+/// Is `color`'s king on `king_square` attacked by any piece of the
+/// opposite color, given the full board occupancy?
+fn is_attacked(board: &[Piece; SQUARE_NB_USIZE], occupied: Bitboard, color: Color, king_square: Square) -> bool {
+    let them = if color == WHITE { BLACK } else { WHITE };
+    for sq in 0..SQUARE_NB_USIZE {
+        let pc = board[sq];
+        if pc == NO_PIECE || color_of(pc) != them {
+            continue;
+        }
+        let from = Square(sq as i8);
+        let attacks = match type_of_piece(pc) {
+            PAWN => bitboard::pawn_captures(them, from),
+            KNIGHT => bitboard::knight_attacks_from(from),
+            BISHOP => bitboard::attacks_bb(BISHOP, from, occupied),
+            ROOK => bitboard::attacks_bb(ROOK, from, occupied),
+            QUEEN => bitboard::attacks_bb(QUEEN, from, occupied),
+            KING => bitboard::king_attacks_from(from),
+            _ => Bitboard(0),
+        };
+        if (attacks & king_square) != Bitboard(0) {
+            return true;
+        }
+    }
+    false
+}
+
+/// Compute the `STATUS_*` bitmask for a fully parsed FEN (the output of
+/// `fen`). A non-zero result still parsed fine syntactically -- it just
+/// describes a position that couldn't arise from legal play.
+pub fn validate(nodes: &[Node]) -> u32 {
+    let (board, side_to_move, castling) = reconstruct(nodes);
+    let mut status = STATUS_VALID;
+
+    let mut white_kings = 0;
+    let mut black_kings = 0;
+    let mut white_pawns = 0;
+    let mut black_pawns = 0;
+    let mut white_pieces = 0;
+    let mut black_pieces = 0;
+    let mut pawns_on_back_rank = false;
+    let mut occupied = Bitboard(0);
+
+    for sq in 0..SQUARE_NB_USIZE {
+        let pc = board[sq];
+        if pc == NO_PIECE {
+            continue;
+        }
+        occupied |= Square(sq as i8);
+        let pt = type_of_piece(pc);
+        let rank = rank_of(Square(sq as i8));
+        match (color_of(pc), pt) {
+            (WHITE, KING) => { white_kings += 1; white_pieces += 1; }
+            (BLACK, KING) => { black_kings += 1; black_pieces += 1; }
+            (WHITE, PAWN) => {
+                white_pawns += 1;
+                white_pieces += 1;
+                if rank == RANK_1 || rank == RANK_8 { pawns_on_back_rank = true; }
+            }
+            (BLACK, PAWN) => {
+                black_pawns += 1;
+                black_pieces += 1;
+                if rank == RANK_1 || rank == RANK_8 { pawns_on_back_rank = true; }
+            }
+            (WHITE, _) => white_pieces += 1,
+            (BLACK, _) => black_pieces += 1,
+            _ => {}
+        }
+    }
+
+    if white_kings == 0 { status |= STATUS_NO_WHITE_KING; }
+    if black_kings == 0 { status |= STATUS_NO_BLACK_KING; }
+    if white_kings > 1 || black_kings > 1 { status |= STATUS_TOO_MANY_KINGS; }
+    if white_pawns > 8 { status |= STATUS_TOO_MANY_WHITE_PAWNS; }
+    if black_pawns > 8 { status |= STATUS_TOO_MANY_BLACK_PAWNS; }
+    if pawns_on_back_rank { status |= STATUS_PAWNS_ON_BACK_RANK; }
+    if white_pieces > 16 { status |= STATUS_TOO_MANY_WHITE_PIECES; }
+    if black_pieces > 16 { status |= STATUS_TOO_MANY_BLACK_PIECES; }
+
+    for &(color, rook_file) in castling.iter() {
+        let home_rank = relative_rank(color, RANK_1);
+        let king_sq = make_square(FILE_E, home_rank);
+        let rook_sq = make_square(rook_file, home_rank);
+        if board[king_sq.0 as usize] != make_piece(color, KING)
+            || board[rook_sq.0 as usize] != make_piece(color, ROOK) {
+            status |= STATUS_BAD_CASTLING_RIGHTS;
+        }
+    }
+
+    for node in nodes {
+        if let Node::EnPassantTargetSquare(sq) = *node {
+            let expected_rank = relative_rank(side_to_move, RANK_6);
+            if rank_of(sq) != expected_rank {
+                status |= STATUS_INVALID_EP_SQUARE;
+            }
+        }
+    }
+
+    if white_kings == 1 && black_kings == 1 {
+        let not_to_move = if side_to_move == WHITE { BLACK } else { WHITE };
+        let mut king_square = SQ_NONE;
+        for sq in 0..SQUARE_NB_USIZE {
+            if board[sq] == make_piece(not_to_move, KING) {
+                king_square = Square(sq as i8);
+            }
+        }
+        if is_attacked(&board, occupied, not_to_move, king_square) {
+            status |= STATUS_OPPOSITE_CHECK;
+        }
+    }
+
+    status
+}
+
+///-----------------------------------------------------------------------------
+// Assembling a `Position` from an already-parsed `Vec<Node>`, as opposed to
+// `Position::set`'s own raw-string path. `placement_field` replays the
+// piece-placement nodes back into FEN text, enforcing the same
+// exactly-eight-files-per-rank rule `set` enforces on its string input;
+// `build_position` stitches the remaining fields together and hands the
+// whole string to `set`, which is still the one place a `Position` actually
+// gets assembled (and where the exactly-one-king-per-side rule lives).
+
+fn node_piece_char(pc: Piece) -> char {
+    let letters = ['p', 'n', 'b', 'r', 'q', 'k'];
+    let letter = letters[(type_of_piece(pc).0 - 1) as usize];
+    if color_of(pc) == WHITE { letter.to_ascii_uppercase() } else { letter }
+}
+
+/// Replay the piece-placement nodes (`Drop`/`Skip`/`NextRank`) into FEN
+/// text, rejecting any rank that over- or underflows eight files.
+fn placement_field(nodes: &[Node]) -> Result<String, position::FenError> {
+    let mut ranks = Vec::new();
+    let mut current = String::new();
+    let mut file = 0i8;
+    let mut skip = 0i8;
+    for node in nodes {
+        match *node {
+            Node::Drop(pc) => {
+                if file >= 8 {
+                    return Err(position::FenError::MalformedRank(ranks.len()));
+                }
+                if skip > 0 {
+                    current.push_str(&skip.to_string());
+                    skip = 0;
+                }
+                current.push(node_piece_char(pc));
+                file += 1;
+            }
+            Node::Skip(n) => {
+                file += n as i8;
+                skip += n as i8;
+                if file > 8 {
+                    return Err(position::FenError::MalformedRank(ranks.len()));
+                }
+            }
+            Node::NextRank => {
+                if file != 8 {
+                    return Err(position::FenError::MalformedRank(ranks.len()));
+                }
+                if skip > 0 {
+                    current.push_str(&skip.to_string());
+                    skip = 0;
+                }
+                ranks.push(current.clone());
+                current.clear();
+                file = 0;
+            }
+            _ => {}
+        }
+    }
+    if file != 8 {
+        return Err(position::FenError::MalformedRank(ranks.len()));
+    }
+    if skip > 0 {
+        current.push_str(&skip.to_string());
+    }
+    ranks.push(current);
+    if ranks.len() != 8 {
+        return Err(position::FenError::WrongRankCount(ranks.len()));
+    }
+    Ok(ranks.join("/"))
+}
+
+/// Collect the `Castle` nodes into a castling-rights field, always as
+/// rook-file letters -- `Position::set` accepts that form whether or not
+/// `chess960` is set -- or `-` if none were claimed.
+fn castling_field(nodes: &[Node]) -> String {
+    let mut s = String::new();
+    for node in nodes {
+        if let Node::Castle(color, file) = *node {
+            let letter = (b'a' + file.0 as u8) as char;
+            s.push(if color == WHITE { letter.to_ascii_uppercase() } else { letter });
+        }
+    }
+    if s.is_empty() { "-".to_string() } else { s }
+}
+
+/// Fold an already-parsed FEN `Vec<Node>` (see `fen`) into a `Position`,
+/// the inverse of `Position::to_fen`/`Position::to_shredder_fen`.
+pub fn build_position(nodes: &[Node], chess960: bool) -> Result<position::Position, position::FenError> {
+    let placement = placement_field(nodes)?;
+    let castling = castling_field(nodes);
+
+    let mut side_to_move = 'w';
+    let mut ep = "-".to_string();
+    let mut halfmove = None;
+    let mut fullmove = None;
+    for node in nodes {
+        match *node {
+            Node::Move(WHITE) => side_to_move = 'w',
+            Node::Move(BLACK) => side_to_move = 'b',
+            Node::EnPassantTargetSquare(sq) => {
+                let f = (b'a' + file_of(sq).0 as u8) as char;
+                let r = (b'1' + rank_of(sq).0 as u8) as char;
+                ep = format!("{}{}", f, r);
+            }
+            Node::HalfMoveClock(h) => halfmove = Some(h),
+            Node::FullMoveNumber(f) => fullmove = Some(f),
+            _ => {}
+        }
+    }
+
+    let mut fen_string = format!("{} {} {} {}", placement, side_to_move, castling, ep);
+    if let Some(h) = halfmove {
+        fen_string.push_str(&format!(" {}", h));
+        if let Some(f) = fullmove {
+            fen_string.push_str(&format!(" {}", f));
+        }
+    }
+
+    position::Position::set(&fen_string, chess960)
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -159,14 +491,14 @@ mod tests {
     fn test_castling_rights() {
 	let fen = &b"KQkq"[..];
 	let expected = vec![
-	    Node::Castle(BLACK, FILE_H), Node::Castle(BLACK, FILE_A), Node::Castle(WHITE, FILE_H), Node::Castle(WHITE, FILE_A)
+	    Node::Castle(WHITE, FILE_H), Node::Castle(WHITE, FILE_A), Node::Castle(BLACK, FILE_H), Node::Castle(BLACK, FILE_A)
 	];
 	assert_eq!(Done(&b""[..], expected), castling_rights(fen));
 	let fen = &b"Kq"[..];
-	let expected = vec![Node::Castle(BLACK, FILE_H), Node::Castle(WHITE, FILE_A)];
+	let expected = vec![Node::Castle(WHITE, FILE_H), Node::Castle(BLACK, FILE_A)];
 	assert_eq!(Done(&b""[..], expected), castling_rights(fen));
 	let fen = &b"Qk"[..];
-	let expected = vec![Node::Castle(BLACK, FILE_A), Node::Castle(WHITE, FILE_H)];
+	let expected = vec![Node::Castle(WHITE, FILE_A), Node::Castle(BLACK, FILE_H)];
 	assert_eq!(Done(&b""[..], expected), castling_rights(fen));
 	let fen = &b"-"[..];
 	let expected = vec![Node::NoCastling];
@@ -174,13 +506,113 @@ mod tests {
 
 	let fen = &b"HAha"[..];
 	let expected = vec![
-	    Node::Castle(BLACK, FILE_H), Node::Castle(BLACK, FILE_A), Node::Castle(WHITE, FILE_H), Node::Castle(WHITE, FILE_A)
+	    Node::Castle(WHITE, FILE_H), Node::Castle(WHITE, FILE_A), Node::Castle(BLACK, FILE_H), Node::Castle(BLACK, FILE_A)
 	];
 	assert_eq!(Done(&b""[..], expected), castling_rights(fen));
 	let fen = &b"AHah"[..];
 	let expected = vec![
-	    Node::Castle(BLACK, FILE_A), Node::Castle(BLACK, FILE_H), Node::Castle(WHITE, FILE_A), Node::Castle(WHITE, FILE_H)
+	    Node::Castle(WHITE, FILE_A), Node::Castle(WHITE, FILE_H), Node::Castle(BLACK, FILE_A), Node::Castle(BLACK, FILE_H)
 	];
 	assert_eq!(Done(&b""[..], expected), castling_rights(fen));
     }
+
+    #[test]
+    fn test_ep_square() {
+        assert_eq!(Done(&b""[..], None), ep_square(b"-"));
+        assert_eq!(Done(&b""[..], Some(SQ_E3)), ep_square(b"e3"));
+        assert_eq!(Done(&b""[..], Some(SQ_D6)), ep_square(b"d6"));
+    }
+
+    #[test]
+    fn test_fen_starting_position() {
+        let nodes = match fen(STARTING_FEN.as_bytes()) {
+            Done(_, nodes) => nodes,
+            _ => { assert!(false, "Unable to parse STARTING_FEN"); return; }
+        };
+        assert!(nodes.contains(&Node::Move(WHITE)));
+        assert!(nodes.contains(&Node::Castle(WHITE, FILE_H)));
+        assert!(nodes.contains(&Node::Castle(WHITE, FILE_A)));
+        assert!(nodes.contains(&Node::Castle(BLACK, FILE_H)));
+        assert!(nodes.contains(&Node::Castle(BLACK, FILE_A)));
+        assert!(nodes.contains(&Node::HalfMoveClock(0)));
+        assert!(nodes.contains(&Node::FullMoveNumber(1)));
+        assert_eq!(STATUS_VALID, validate(&nodes));
+    }
+
+    #[test]
+    fn test_validate_missing_king() {
+        let nodes = match fen(b"rnbq1bnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQ1BNR w - - 0 1") {
+            Done(_, nodes) => nodes,
+            _ => { assert!(false, "Unable to parse fen missing both kings"); return; }
+        };
+        let status = validate(&nodes);
+        assert!(status & STATUS_NO_WHITE_KING != 0);
+        assert!(status & STATUS_NO_BLACK_KING != 0);
+    }
+
+    #[test]
+    fn test_validate_pawns_on_back_rank() {
+        let nodes = match fen(b"rnbqkbnP/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1") {
+            Done(_, nodes) => nodes,
+            _ => { assert!(false, "Unable to parse fen with a pawn on the back rank"); return; }
+        };
+        assert!(validate(&nodes) & STATUS_PAWNS_ON_BACK_RANK != 0);
+    }
+
+    #[test]
+    fn test_validate_opposite_check() {
+        // White, to move, has already moved a rook onto e8 attacking the
+        // black king -- an illegal position, since black should have
+        // resolved the check on their own last move.
+        let nodes = match fen(b"4R1k1/8/8/8/8/8/8/4K3 w - - 0 1") {
+            Done(_, nodes) => nodes,
+            _ => { assert!(false, "Unable to parse opposite-check fen"); return; }
+        };
+        assert!(validate(&nodes) & STATUS_OPPOSITE_CHECK != 0);
+    }
+
+    #[test]
+    fn test_build_position_starting_fen() {
+        let nodes = match fen(STARTING_FEN.as_bytes()) {
+            Done(_, nodes) => nodes,
+            _ => { assert!(false, "Unable to parse STARTING_FEN"); return; }
+        };
+        let pos = build_position(&nodes, false).unwrap();
+        assert_eq!(pos.to_fen(), STARTING_FEN);
+    }
+
+    #[test]
+    fn test_build_position_rejects_short_rank() {
+        let nodes = match fen(b"rnbqkbnr/pppppppp/8/8/8/8/PPPPPPP/RNBQKBNR w KQkq - 0 1") {
+            Done(_, nodes) => nodes,
+            _ => { assert!(false, "Unable to parse fen with a short rank"); return; }
+        };
+        match build_position(&nodes, false) {
+            Err(position::FenError::MalformedRank(_)) => {}
+            Err(e) => assert!(false, "expected MalformedRank, got {:?}", e),
+            Ok(_) => assert!(false, "expected MalformedRank, got Ok"),
+        }
+    }
+
+    #[test]
+    fn test_build_position_preserves_ep_square() {
+        let fen_str = "rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 3";
+        let nodes = match fen(fen_str.as_bytes()) {
+            Done(_, nodes) => nodes,
+            _ => { assert!(false, "Unable to parse fen with an en-passant target"); return; }
+        };
+        let pos = build_position(&nodes, false).unwrap();
+        assert_eq!(pos.to_fen(), fen_str);
+    }
+
+    #[test]
+    fn test_build_position_shredder_castling_rights() {
+        let fen_str = "1rkr2nr/8/8/8/8/8/8/1RKR2NR w HBhb - 0 1";
+        let nodes = match fen(fen_str.as_bytes()) {
+            Done(_, nodes) => nodes,
+            _ => { assert!(false, "Unable to parse Chess960 fen"); return; }
+        };
+        let pos = build_position(&nodes, true).unwrap();
+        assert_eq!(pos.to_shredder_fen(), fen_str);
+    }
 }
@@ -0,0 +1,183 @@
+// This file is part of the samson library.
+//
+// Copyright (C) 2017 Lakin Wecker <lakin@wecker.ca>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+///-------------------------------------------------------------------------------------------------
+/// `pgn::game_node` already tokenizes a movetext stream -- move numbers,
+/// comments, NAGs, `san::san_move`, and the `(`/`)` variation markers -- and
+/// `pgn::nest_variations` already folds those markers into `pgn::Node::Variation`.
+/// What's still missing is a representation callers actually want to walk: a
+/// tree of half-moves, where each move carries the NAGs/comments that trailed
+/// it and the variations that branched off right after it, rather than a flat
+/// sibling list that happens to contain move and non-move tokens side by side.
+/// This module builds that tree on top of the existing token stream instead of
+/// re-parsing anything.
+///-------------------------------------------------------------------------------------------------
+
+use super::pgn;
+use super::san;
+
+///-------------------------------------------------------------------------------------------------
+/// A single ply: the resolved SAN move, any NAGs/comments that followed it
+/// before the next move, and the variations (alternatives to the move that
+/// comes next in this line) that branched off at this point.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HalfMove<'a> {
+    pub mv: san::Node,
+    pub nags: Vec<pgn::NumericAnnotationGlyph>,
+    pub comments: Vec<&'a [u8]>,
+    pub variations: Vec<Vec<HalfMove<'a>>>
+}
+
+impl<'a> HalfMove<'a> {
+    fn new(mv: san::Node) -> HalfMove<'a> {
+        HalfMove { mv: mv, nags: Vec::new(), comments: Vec::new(), variations: Vec::new() }
+    }
+}
+
+///-------------------------------------------------------------------------------------------------
+/// Why `fold_movetext` couldn't build a half-move tree out of a node list.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FoldError {
+    /// The variation markers weren't nested yet (`pgn::nest_variations`
+    /// failed, or raw `game_node_list` output was passed in directly).
+    Nesting(pgn::NestingError),
+    /// A NAG, comment or variation appeared before any move to attach it to.
+    NoPrecedingMove
+}
+
+///-------------------------------------------------------------------------------------------------
+/// Fold already-nested `Node`s (see `pgn::nest_variations`) into a half-move
+/// tree. `MoveNumber` and `EscapeComment` carry no information once the moves
+/// are in tree form, so they're dropped here.
+fn fold_nested<'a>(nodes: Vec<pgn::Node<'a>>) -> Result<Vec<HalfMove<'a>>, FoldError> {
+    let mut result: Vec<HalfMove<'a>> = Vec::new();
+    for node in nodes {
+        match node {
+            pgn::Node::Move(mv) => result.push(HalfMove::new(mv)),
+            pgn::Node::Nag(nag) => {
+                match result.last_mut() {
+                    Some(last) => last.nags.push(nag),
+                    None => return Err(FoldError::NoPrecedingMove)
+                }
+            },
+            pgn::Node::Comment(text) => {
+                match result.last_mut() {
+                    Some(last) => last.comments.push(text),
+                    None => return Err(FoldError::NoPrecedingMove)
+                }
+            },
+            pgn::Node::Variation(children) => {
+                let folded = match fold_nested(children) {
+                    Ok(folded) => folded,
+                    Err(e) => return Err(e)
+                };
+                match result.last_mut() {
+                    Some(last) => last.variations.push(folded),
+                    None => return Err(FoldError::NoPrecedingMove)
+                }
+            },
+            pgn::Node::MoveNumber(_, _) | pgn::Node::EscapeComment(_) => {},
+            pgn::Node::StartVariation | pgn::Node::EndVariation => {
+                return Err(FoldError::Nesting(pgn::NestingError::UnexpectedEndVariation));
+            }
+        }
+    }
+    Ok(result)
+}
+
+///-------------------------------------------------------------------------------------------------
+/// Parse a flat movetext token list (as produced by `pgn::game_node_list`, or
+/// taken straight from `pgn::Game::nodes`) into a tree of half-moves.
+pub fn fold_movetext<'a>(nodes: Vec<pgn::Node<'a>>) -> Result<Vec<HalfMove<'a>>, FoldError> {
+    match pgn::nest_variations(nodes) {
+        Ok(nested) => fold_nested(nested),
+        Err(e) => Err(FoldError::Nesting(e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use super::super::super::types::*;
+    use super::super::san;
+    use super::super::pgn;
+
+    fn mv(square: Square) -> san::Node {
+        san::Node::Move(
+            PAWN, san::Source::None, san::MoveOrCapture::Move, square,
+            san::Promotion::None, san::Check::None, Vec::new()
+        )
+    }
+
+    #[test]
+    fn test_fold_movetext_flat_line() {
+        let nodes = vec![
+            pgn::Node::MoveNumber(1, pgn::Periods::One),
+            pgn::Node::Move(mv(SQ_E4)),
+            pgn::Node::Move(mv(SQ_E5)),
+        ];
+        let half_moves = fold_movetext(nodes).unwrap();
+        assert_eq!(2, half_moves.len());
+        assert_eq!(mv(SQ_E4), half_moves[0].mv);
+        assert_eq!(mv(SQ_E5), half_moves[1].mv);
+    }
+
+    #[test]
+    fn test_fold_movetext_attaches_trailing_comments_and_nags() {
+        let nodes = vec![
+            pgn::Node::Move(mv(SQ_E4)),
+            pgn::Node::Nag(pgn::NumericAnnotationGlyph(1)),
+            pgn::Node::Comment(&b"best by test"[..]),
+        ];
+        let half_moves = fold_movetext(nodes).unwrap();
+        assert_eq!(1, half_moves.len());
+        assert_eq!(vec![pgn::NumericAnnotationGlyph(1)], half_moves[0].nags);
+        assert_eq!(vec![&b"best by test"[..]], half_moves[0].comments);
+    }
+
+    #[test]
+    fn test_fold_movetext_nests_variations_under_the_move_they_branch_from() {
+        let nodes = vec![
+            pgn::Node::Move(mv(SQ_E4)),
+            pgn::Node::StartVariation,
+            pgn::Node::Move(mv(SQ_C4)),
+            pgn::Node::EndVariation,
+            pgn::Node::Move(mv(SQ_E5)),
+        ];
+        let half_moves = fold_movetext(nodes).unwrap();
+        assert_eq!(2, half_moves.len());
+        assert_eq!(1, half_moves[0].variations.len());
+        assert_eq!(mv(SQ_C4), half_moves[0].variations[0][0].mv);
+        assert!(half_moves[0].variations[0][0].variations.is_empty());
+    }
+
+    #[test]
+    fn test_fold_movetext_rejects_comment_with_no_preceding_move() {
+        let nodes = vec![pgn::Node::Comment(&b"huh"[..])];
+        assert_eq!(Err(FoldError::NoPrecedingMove), fold_movetext(nodes));
+    }
+
+    #[test]
+    fn test_fold_movetext_rejects_unclosed_variation() {
+        let nodes = vec![pgn::Node::Move(mv(SQ_E4)), pgn::Node::StartVariation];
+        match fold_movetext(nodes) {
+            Err(FoldError::Nesting(pgn::NestingError::UnclosedVariation)) => {},
+            other => assert!(false, "expected UnclosedVariation, got {:?}", other)
+        }
+    }
+}
@@ -23,7 +23,9 @@ use super::super::types::*;
 use nom::*;
 use parser::san;
 use parser::bom;
+use parser::fen;
 
+use std::fmt;
 use std::str;
 use std::str::FromStr;
 
@@ -47,6 +49,10 @@ pub enum Tag<'a> {
     White(&'a [u8]),
     Black(&'a [u8]),
     Result(&'a [u8]),
+    /// `[SetUp "1"]`: the game doesn't start from the standard array.
+    SetUp(&'a [u8]),
+    /// `[FEN "..."]`: the starting position, present whenever `SetUp` is.
+    Fen(&'a [u8]),
     Other(&'a [u8], &'a [u8])
 }
 
@@ -54,6 +60,88 @@ pub enum Tag<'a> {
 #[derive(Clone, Debug, PartialEq, PartialOrd, Ord, Eq, Hash)]
 pub struct NumericAnnotationGlyph(pub u64);
 
+impl NumericAnnotationGlyph {
+    /// Decode this glyph's meaning from the standard NAG table.
+    pub fn annotation(&self) -> Annotation { Annotation::from_nag(self.0) }
+}
+
+impl From<Annotation> for NumericAnnotationGlyph {
+    fn from(annotation: Annotation) -> NumericAnnotationGlyph { NumericAnnotationGlyph(annotation.to_nag()) }
+}
+
+///-------------------------------------------------------------------------------------------------
+/// The semantic meaning behind a Numeric Annotation Glyph, decoded from the
+/// standard NAG table (PGN spec, appendix A). This is also the canonical
+/// form for a SAN suffix annotation (`!`, `?`, `!?`, ...), so a move graded
+/// either way -- `1. e4! $1` -- collapses to the same value.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum Annotation {
+    Good,
+    Mistake,
+    Brilliant,
+    Blunder,
+    Interesting,
+    Dubious,
+    Forced,
+    Even,
+    Unclear,
+    WhiteSlightAdvantage,
+    BlackSlightAdvantage,
+    WhiteModerateAdvantage,
+    BlackModerateAdvantage,
+    WhiteDecisiveAdvantage,
+    BlackDecisiveAdvantage,
+    /// A NAG number the table above doesn't give a name to -- carried
+    /// losslessly rather than dropped.
+    Other(u64)
+}
+
+impl Annotation {
+    /// Look up a NAG number's meaning in the standard table.
+    pub fn from_nag(nag: u64) -> Annotation {
+        match nag {
+            1 => Annotation::Good,
+            2 => Annotation::Mistake,
+            3 => Annotation::Brilliant,
+            4 => Annotation::Blunder,
+            5 => Annotation::Interesting,
+            6 => Annotation::Dubious,
+            7 => Annotation::Forced,
+            10 => Annotation::Even,
+            13 => Annotation::Unclear,
+            14 => Annotation::WhiteSlightAdvantage,
+            15 => Annotation::BlackSlightAdvantage,
+            16 => Annotation::WhiteModerateAdvantage,
+            17 => Annotation::BlackModerateAdvantage,
+            18 => Annotation::WhiteDecisiveAdvantage,
+            19 => Annotation::BlackDecisiveAdvantage,
+            n => Annotation::Other(n)
+        }
+    }
+
+    /// The inverse of `from_nag`: the NAG number this annotation encodes as.
+    pub fn to_nag(&self) -> u64 {
+        match *self {
+            Annotation::Good => 1,
+            Annotation::Mistake => 2,
+            Annotation::Brilliant => 3,
+            Annotation::Blunder => 4,
+            Annotation::Interesting => 5,
+            Annotation::Dubious => 6,
+            Annotation::Forced => 7,
+            Annotation::Even => 10,
+            Annotation::Unclear => 13,
+            Annotation::WhiteSlightAdvantage => 14,
+            Annotation::BlackSlightAdvantage => 15,
+            Annotation::WhiteModerateAdvantage => 16,
+            Annotation::BlackModerateAdvantage => 17,
+            Annotation::WhiteDecisiveAdvantage => 18,
+            Annotation::BlackDecisiveAdvantage => 19,
+            Annotation::Other(n) => n
+        }
+    }
+}
+
 ///-------------------------------------------------------------------------------------------------
 #[derive(Clone, PartialEq, PartialOrd, Eq, Ord, Debug, Hash)]
 pub enum Periods {
@@ -89,9 +177,24 @@ pub enum Node<'a> {
 ///-------------------------------------------------------------------------------------------------
 #[derive(Clone, Debug, PartialEq)]
 pub struct Game<'a> {
+    /// `%`-prefixed lines that appeared before the tag roster or between it
+    /// and the movetext, e.g. `% BOOKTITLE = ...`. Escape comments that
+    /// appear within the movetext itself show up as `Node::EscapeComment`
+    /// instead, interleaved with the rest of `nodes`.
+    pub escape_comments: Vec<&'a [u8]>,
     pub tags: Vec<Tag<'a>>,
     pub nodes: Vec<Node<'a>>,
-    pub result: Result
+    pub result: Result,
+    /// The position the movetext replays from, taken from the `FEN` tag
+    /// when present (and `None` for a standard game, or if the tag's value
+    /// didn't parse as a FEN string). `san::san_move` itself still only
+    /// understands the standard 8x8 board and piece alphabet -- widening it
+    /// to accept variant geometries (e.g. Capablanca's 10x8 board and its
+    /// Archbishop/Chancellor pieces) would mean threading a board/alphabet
+    /// config through `types`, `bitboard` and the SAN parser together, which
+    /// is a larger cross-cutting change than this tag-parsing fix and is
+    /// left for a dedicated pass.
+    pub starting_position: Option<Vec<fen::Node>>
 }
 
 ///-------------------------------------------------------------------------------------------------
@@ -118,6 +221,9 @@ named!(pub close_parenthesis_token, tag!(")"));
 ///-------------------------------------------------------------------------------------------------
 named!(pub escape_comment, preceded!(tag!("%"), is_not!("\n")));
 
+///-------------------------------------------------------------------------------------------------
+named!(pub line_comment, preceded!(char!(';'), is_not!("\n")));
+
 ///-------------------------------------------------------------------------------------------------
 named!(pub nag_token<NumericAnnotationGlyph>,
     map!(preceded!(char!('$'), integer_token), |i| { NumericAnnotationGlyph(i) })
@@ -154,6 +260,10 @@ named!(pub tag_pair<Tag>,
                 Tag::Black(value)
             } else if key == &b"Result"[..] {
                 Tag::Result(value)
+            } else if key == &b"SetUp"[..] {
+                Tag::SetUp(value)
+            } else if key == &b"FEN"[..] {
+                Tag::Fen(value)
             } else {
                 Tag::Other(key, value)
             }
@@ -184,6 +294,8 @@ named!(pub game_node<Node>,
         map!(ws!(close_parenthesis_token), |_| { Node::EndVariation }) |
         map!(ws!(nag_token), |n| { Node::Nag(n) }) |
         map!(ws!(commentary_token), |c| { Node::Comment(c) }) |
+        map!(ws!(line_comment), |c| { Node::Comment(c) }) |
+        map!(ws!(escape_comment), |c| { Node::EscapeComment(c) }) |
         map!(
             do_parse!(
                 num: ws!(complete!(integer_token)) >>
@@ -214,34 +326,518 @@ named!(pub game_node<Node>,
 ///-------------------------------------------------------------------------------------------------
 named!(pub game_node_list<Vec<Node> >, many1!(game_node));
 
+///-------------------------------------------------------------------------------------------------
+/// Why `nest_variations` couldn't fold a flat token stream into a tree.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NestingError {
+    /// An `EndVariation` appeared with nothing but the mainline open.
+    UnexpectedEndVariation,
+    /// The stream ended with one or more variations still open.
+    UnclosedVariation,
+}
+
+///-------------------------------------------------------------------------------------------------
+/// Fold the flat `StartVariation`/`EndVariation` tokens `game_node_list`
+/// produces into properly nested `Node::Variation` values, so callers don't
+/// have to balance the parentheses themselves. The flat parser is left as
+/// is for callers who want the raw token stream.
+pub fn nest_variations(nodes: Vec<Node>) -> ::std::result::Result<Vec<Node>, NestingError> {
+    let mut stack: Vec<Vec<Node>> = vec![Vec::new()];
+    for node in nodes {
+        match node {
+            Node::StartVariation => stack.push(Vec::new()),
+            Node::EndVariation => {
+                if stack.len() < 2 {
+                    return Err(NestingError::UnexpectedEndVariation);
+                }
+                let popped = stack.pop().unwrap();
+                stack.last_mut().unwrap().push(Node::Variation(popped));
+            },
+            other => stack.last_mut().unwrap().push(other),
+        }
+    }
+    if stack.len() != 1 {
+        return Err(NestingError::UnclosedVariation);
+    }
+    Ok(stack.pop().unwrap())
+}
+
+///-------------------------------------------------------------------------------------------------
+/// The SAN moves of a mainline (or of a single variation) in order,
+/// skipping move numbers, comments, and NAGs, without descending into any
+/// variation branching off it. Understands both the flat token stream
+/// (`Node::StartVariation`/`Node::EndVariation`) `game_node_list` produces
+/// and the nested `Node::Variation` form `nest_variations` builds, so it
+/// works on a `Game`'s nodes either way.
+pub struct MainlineMoves<'a, 'b: 'a> {
+    nodes: ::std::slice::Iter<'a, Node<'b>>,
+    depth: usize,
+}
+
+impl<'a, 'b> Iterator for MainlineMoves<'a, 'b> {
+    type Item = &'a san::Node;
+
+    fn next(&mut self) -> Option<&'a san::Node> {
+        while let Some(node) = self.nodes.next() {
+            match *node {
+                Node::StartVariation => self.depth += 1,
+                Node::EndVariation => { if self.depth > 0 { self.depth -= 1; } },
+                Node::Variation(_) => {},
+                Node::Move(ref mv) if self.depth == 0 => return Some(mv),
+                _ => {},
+            }
+        }
+        None
+    }
+}
+
+/// Walk `nodes`' own mainline, the same line `MainlineMoves` documents.
+pub fn mainline_moves<'a, 'b>(nodes: &'a [Node<'b>]) -> MainlineMoves<'a, 'b> {
+    MainlineMoves { nodes: nodes.iter(), depth: 0 }
+}
+
+///-------------------------------------------------------------------------------------------------
+/// Each top-level `Node::Variation` branching off `nodes`, in order. Only
+/// meaningful after `nest_variations`; a still-flat token stream has no
+/// `Node::Variation`s to find and this iterator simply yields nothing.
+/// Recurse into a yielded slice with `variations`/`mainline_moves` again to
+/// walk a sub-variation's own branches.
+pub struct Variations<'a, 'b: 'a> {
+    nodes: ::std::slice::Iter<'a, Node<'b>>,
+}
+
+impl<'a, 'b> Iterator for Variations<'a, 'b> {
+    type Item = &'a [Node<'b>];
+
+    fn next(&mut self) -> Option<&'a [Node<'b>]> {
+        for node in &mut self.nodes {
+            if let Node::Variation(ref children) = *node {
+                return Some(children);
+            }
+        }
+        None
+    }
+}
+
+/// The top-level variations branching off `nodes`, the same set
+/// `Variations` documents.
+pub fn variations<'a, 'b>(nodes: &'a [Node<'b>]) -> Variations<'a, 'b> {
+    Variations { nodes: nodes.iter() }
+}
+
+impl<'a> Game<'a> {
+    /// This game's mainline moves; see `mainline_moves`.
+    pub fn mainline_moves<'b>(&'b self) -> MainlineMoves<'b, 'a> {
+        mainline_moves(&self.nodes)
+    }
+
+    /// This game's top-level variations; see `variations`.
+    pub fn variations<'b>(&'b self) -> Variations<'b, 'a> {
+        variations(&self.nodes)
+    }
+}
+
 ///-------------------------------------------------------------------------------------------------
 named!(pub game_node_list_with_result<(Vec<Node>, Result)>, many_till!(game_node, game_result));
 
-// TODO: find a more elegant way to deal with the silly escape comments.
-//       Q: Why does pgn have such ambiguous rules. So can an escape comment
-//       appear in the middle of a tag list or set of moves/commentary?
-//       What about in a commentary itself? 
-//       A: Tide goes in, Tide goes out. You can't explain that.
-//
-//       Also, we are ignoring the escape comments for now. 
+///-------------------------------------------------------------------------------------------------
+/// Look for a `[FEN "..."]` tag and, if present, parse it into the nodes
+/// `fen::build_position` folds into a starting position. Returns `None` for a
+/// standard game, or if the tag's value isn't a FEN string we can parse.
+fn starting_position_from_tags(tags: &[Tag]) -> Option<Vec<fen::Node>> {
+    use nom::IResult::Done;
+    for tag in tags {
+        if let Tag::Fen(value) = *tag {
+            return match fen::fen(value) {
+                Done(_, nodes) => Some(nodes),
+                _ => None
+            };
+        }
+    }
+    None
+}
+
 named!(pub game<Game>,
     map!(
         do_parse!(
-            many0!(escape_comment) >>
+            pre_tags: many0!(escape_comment) >>
             tags: ws!(tag_list) >>
-            many0!(escape_comment) >>
+            pre_movetext: many0!(escape_comment) >>
             nodes_with_result: ws!(game_node_list_with_result) >>
-            (tags, nodes_with_result)
+            (pre_tags, pre_movetext, tags, nodes_with_result)
         ),
-        |(tags, nodes_with_result)| {
+        |(pre_tags, pre_movetext, tags, nodes_with_result)| {
             let nodes_with_result: (Vec<Node>, Result) = nodes_with_result;
-            Game{tags: tags, nodes:nodes_with_result.0, result: nodes_with_result.1}
+            let mut escape_comments = pre_tags;
+            escape_comments.extend(pre_movetext);
+            let starting_position = starting_position_from_tags(&tags);
+            Game{
+                escape_comments: escape_comments,
+                tags: tags,
+                nodes: nodes_with_result.0,
+                result: nodes_with_result.1,
+                starting_position: starting_position
+            }
         }
     )
 );
-named!(pub pgn<Vec<Game> >,
-    do_parse!(opt!(bom::utf_8_bom) >> games: many0!(game) >> (games))
-);
+/// Where `games` failed to parse the next game while streaming a database,
+/// given as the byte offset from the start of the input it was handed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ParseError {
+    pub offset: usize
+}
+
+///-------------------------------------------------------------------------------------------------
+/// Skip whatever separates one game from the next -- whitespace and escape
+/// comments -- without parsing anything else.
+fn skip_noise(mut input: &[u8]) -> &[u8] {
+    use nom::IResult::Done;
+    loop {
+        let before = input.len();
+        if let Done(rest, _) = complete!(input, multispace) { input = rest; }
+        if let Done(rest, _) = complete!(input, escape_comment) { input = rest; }
+        if input.len() == before { break; }
+    }
+    input
+}
+
+///-------------------------------------------------------------------------------------------------
+/// Streams the games out of a multi-game PGN database one at a time, holding
+/// only the remaining unparsed tail rather than collecting the whole database
+/// into memory the way `pgn` used to.
+pub struct Games<'a> {
+    remaining: &'a [u8],
+    offset: usize,
+    done: bool
+}
+
+impl<'a> Iterator for Games<'a> {
+    type Item = ::std::result::Result<Game<'a>, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        use nom::IResult::Done;
+        if self.done {
+            return None;
+        }
+        let before = self.remaining.len();
+        self.remaining = skip_noise(self.remaining);
+        self.offset += before - self.remaining.len();
+        if self.remaining.is_empty() {
+            self.done = true;
+            return None;
+        }
+        match game(self.remaining) {
+            Done(rest, g) => {
+                self.offset += self.remaining.len() - rest.len();
+                self.remaining = rest;
+                Some(Ok(g))
+            },
+            _ => {
+                self.done = true;
+                Some(Err(ParseError{ offset: self.offset }))
+            }
+        }
+    }
+}
+
+///-------------------------------------------------------------------------------------------------
+/// Stream the games out of `input` one at a time, skipping a leading
+/// byte-order-mark if present. Unlike `pgn`, this holds only the current
+/// game in memory at once, so it scales to the multi-million-game archives
+/// a single `Vec<Game>` can't.
+pub fn games(input: &[u8]) -> Games {
+    use nom::IResult::Done;
+    let (offset, remaining) = match bom::utf_8_bom(input) {
+        Done(rest, _) => (input.len() - rest.len(), rest),
+        _ => (0, input)
+    };
+    Games { remaining: remaining, offset: offset, done: false }
+}
+
+///-------------------------------------------------------------------------------------------------
+/// Collects every game out of a database up front. Kept for callers who want
+/// a `Vec<Game>` and don't mind the memory cost; `games` is the streaming
+/// equivalent and is preferred for large archives.
+pub fn pgn(input: &[u8]) -> Vec<Game> {
+    games(input).filter_map(|g| g.ok()).collect()
+}
+
+///-------------------------------------------------------------------------------------------------
+/// Writing a game back out to text is split the same way formatting is split
+/// elsewhere in the codebase: the traversal over `Node`s is shared, and only
+/// the token formatting differs between backends (plain PGN export format vs.
+/// a typeset form such as LaTeX). Implement this trait to add a new backend.
+pub trait MovetextFormatter {
+    fn write_move_number(&self, n: u64, periods: Periods, out: &mut String);
+    fn write_move(&self, mv: &san::Node, out: &mut String);
+    fn write_nag(&self, nag: NumericAnnotationGlyph, out: &mut String);
+    fn write_comment(&self, text: &[u8], out: &mut String);
+    fn write_escape_comment(&self, text: &[u8], out: &mut String);
+    fn open_variation(&self, out: &mut String);
+    fn close_variation(&self, out: &mut String);
+
+    /// Whether nested variations should be broken onto their own, indented
+    /// lines. Plain PGN export format keeps everything on the wrapped main
+    /// line; typeset backends tend to want the structure visible.
+    fn indent_variations(&self) -> bool { false }
+}
+
+///-------------------------------------------------------------------------------------------------
+/// The plain PGN export format: move numbers as `1.`/`1...`, NAGs as `$1`,
+/// comments in braces, variations in parentheses.
+pub struct PgnFormatter;
+
+impl MovetextFormatter for PgnFormatter {
+    fn write_move_number(&self, n: u64, periods: Periods, out: &mut String) {
+        out.push_str(&n.to_string());
+        match periods {
+            Periods::None => {},
+            Periods::One => out.push('.'),
+            Periods::Three => out.push_str("..."),
+            Periods::Other => out.push_str("...."),
+        }
+    }
+    fn write_move(&self, mv: &san::Node, out: &mut String) { san::write_san_move(mv, out); }
+    fn write_nag(&self, nag: NumericAnnotationGlyph, out: &mut String) {
+        out.push('$');
+        out.push_str(&nag.0.to_string());
+    }
+    fn write_comment(&self, text: &[u8], out: &mut String) {
+        out.push('{');
+        out.push_str(&String::from_utf8_lossy(text));
+        out.push('}');
+    }
+    fn write_escape_comment(&self, text: &[u8], out: &mut String) {
+        out.push('%');
+        out.push_str(&String::from_utf8_lossy(text));
+        out.push('\n');
+    }
+    fn open_variation(&self, out: &mut String) { out.push('('); }
+    fn close_variation(&self, out: &mut String) { out.push(')'); }
+}
+
+///-------------------------------------------------------------------------------------------------
+/// A typeset export suitable for inclusion in a LaTeX document: moves in
+/// bold, comments in italics, NAGs as superscripts, variations indented
+/// rather than merely parenthesized.
+pub struct LatexFormatter;
+
+impl MovetextFormatter for LatexFormatter {
+    fn write_move_number(&self, n: u64, periods: Periods, out: &mut String) {
+        out.push_str(&n.to_string());
+        match periods {
+            Periods::None => {},
+            Periods::One => out.push('.'),
+            Periods::Three => out.push_str("..."),
+            Periods::Other => out.push_str("...."),
+        }
+    }
+    fn write_move(&self, mv: &san::Node, out: &mut String) {
+        out.push_str("\\textbf{");
+        san::write_san_move(mv, out);
+        out.push('}');
+    }
+    fn write_nag(&self, nag: NumericAnnotationGlyph, out: &mut String) {
+        out.push_str("\\textsuperscript{$");
+        out.push_str(&nag.0.to_string());
+        out.push_str("$}");
+    }
+    fn write_comment(&self, text: &[u8], out: &mut String) {
+        out.push_str("\\textit{");
+        out.push_str(&String::from_utf8_lossy(text));
+        out.push('}');
+    }
+    fn write_escape_comment(&self, text: &[u8], out: &mut String) {
+        out.push_str("% ");
+        out.push_str(&String::from_utf8_lossy(text));
+        out.push('\n');
+    }
+    fn open_variation(&self, _out: &mut String) {}
+    fn close_variation(&self, _out: &mut String) {}
+
+    fn indent_variations(&self) -> bool { true }
+}
+
+///-------------------------------------------------------------------------------------------------
+/// Accumulates movetext into a single `String`, wrapping at 80 columns the
+/// way the PGN export format spec expects, and inserting the indentation
+/// typeset backends use for nested variations.
+struct Wrapper {
+    out: String,
+    col: usize,
+    suppress_next_space: bool,
+}
+
+impl Wrapper {
+    fn new() -> Wrapper { Wrapper{ out: String::new(), col: 0, suppress_next_space: false } }
+
+    fn push_token(&mut self, token: &str, space_before: bool) {
+        let space_before = space_before && !self.suppress_next_space;
+        self.suppress_next_space = false;
+        if space_before && self.col > 0 {
+            if self.col + 1 + token.len() > 80 {
+                self.out.push('\n');
+                self.col = 0;
+            } else {
+                self.out.push(' ');
+                self.col += 1;
+            }
+        }
+        self.out.push_str(token);
+        self.col += token.len();
+    }
+
+    fn newline_indent(&mut self, n: usize) {
+        self.out.push('\n');
+        for _ in 0..n { self.out.push(' '); }
+        self.col = n;
+    }
+}
+
+///-------------------------------------------------------------------------------------------------
+/// Walk a (possibly `nest_variations`-nested, possibly still flat) sequence
+/// of `Node`s, writing each one with `formatter`. Flat `StartVariation`/
+/// `EndVariation` tokens are rendered directly; a nested `Node::Variation`
+/// recurses one level deeper.
+fn write_nodes<F: MovetextFormatter>(nodes: &[Node], formatter: &F, w: &mut Wrapper, depth: usize) {
+    for node in nodes {
+        match *node {
+            Node::MoveNumber(n, ref periods) => {
+                let mut s = String::new();
+                formatter.write_move_number(n, periods.clone(), &mut s);
+                w.push_token(&s, true);
+            },
+            Node::Move(ref mv) => {
+                let mut s = String::new();
+                formatter.write_move(mv, &mut s);
+                w.push_token(&s, true);
+            },
+            Node::Nag(ref nag) => {
+                let mut s = String::new();
+                formatter.write_nag(nag.clone(), &mut s);
+                w.push_token(&s, false);
+            },
+            Node::Comment(text) => {
+                let mut s = String::new();
+                formatter.write_comment(text, &mut s);
+                w.push_token(&s, true);
+            },
+            Node::EscapeComment(text) => {
+                let mut s = String::new();
+                formatter.write_escape_comment(text, &mut s);
+                w.push_token(&s, true);
+            },
+            Node::StartVariation => {
+                let mut s = String::new();
+                formatter.open_variation(&mut s);
+                w.push_token(&s, true);
+                w.suppress_next_space = true;
+            },
+            Node::EndVariation => {
+                let mut s = String::new();
+                formatter.close_variation(&mut s);
+                w.push_token(&s, false);
+            },
+            Node::Variation(ref children) => {
+                if formatter.indent_variations() {
+                    w.newline_indent((depth + 1) * 2);
+                    write_nodes(children, formatter, w, depth + 1);
+                } else {
+                    let mut open = String::new();
+                    formatter.open_variation(&mut open);
+                    w.push_token(&open, true);
+                    w.suppress_next_space = true;
+                    write_nodes(children, formatter, w, depth + 1);
+                    let mut close = String::new();
+                    formatter.close_variation(&mut close);
+                    w.push_token(&close, false);
+                }
+            },
+        }
+    }
+}
+
+///-------------------------------------------------------------------------------------------------
+/// Emit the Seven Tag Roster in its canonical order (only the tags that are
+/// actually present), followed by any other tags in their original order.
+fn write_tags(tags: &[Tag], out: &mut String) {
+    fn write_tag_pair(name: &str, value: &[u8], out: &mut String) {
+        out.push('[');
+        out.push_str(name);
+        out.push_str(" \"");
+        out.push_str(&String::from_utf8_lossy(value));
+        out.push_str("\"]\n");
+    }
+    for &name in ["Event", "Site", "Date", "Round", "White", "Black", "Result"].iter() {
+        for tag in tags {
+            match *tag {
+                Tag::Event(v) if name == "Event" => write_tag_pair(name, v, out),
+                Tag::Site(v) if name == "Site" => write_tag_pair(name, v, out),
+                Tag::Date(v) if name == "Date" => write_tag_pair(name, v, out),
+                Tag::Round(v) if name == "Round" => write_tag_pair(name, v, out),
+                Tag::White(v) if name == "White" => write_tag_pair(name, v, out),
+                Tag::Black(v) if name == "Black" => write_tag_pair(name, v, out),
+                Tag::Result(v) if name == "Result" => write_tag_pair(name, v, out),
+                _ => {},
+            }
+        }
+    }
+    for tag in tags {
+        match *tag {
+            Tag::SetUp(v) => write_tag_pair("SetUp", v, out),
+            Tag::Fen(v) => write_tag_pair("FEN", v, out),
+            Tag::Other(name, value) => write_tag_pair(&String::from_utf8_lossy(name), value, out),
+            _ => {},
+        }
+    }
+}
+
+///-------------------------------------------------------------------------------------------------
+fn write_result(result: Result, out: &mut String) {
+    out.push_str(match result {
+        Result::WhiteWin => "1-0",
+        Result::BlackWin => "0-1",
+        Result::Draw => "1/2-1/2",
+        Result::Other => "*",
+    });
+}
+
+///-------------------------------------------------------------------------------------------------
+pub fn write_game<F: MovetextFormatter>(game: &Game, formatter: &F, out: &mut String) {
+    for comment in &game.escape_comments {
+        formatter.write_escape_comment(comment, out);
+    }
+    write_tags(&game.tags, out);
+    out.push('\n');
+    let mut w = Wrapper::new();
+    write_nodes(&game.nodes, formatter, &mut w, 0);
+    let mut result = String::new();
+    write_result(game.result.clone(), &mut result);
+    w.push_token(&result, true);
+    out.push_str(&w.out);
+    out.push('\n');
+}
+
+///-------------------------------------------------------------------------------------------------
+pub fn write_pgn(game: &Game) -> String {
+    let mut out = String::new();
+    write_game(game, &PgnFormatter, &mut out);
+    out
+}
+
+///-------------------------------------------------------------------------------------------------
+pub fn write_latex(game: &Game) -> String {
+    let mut out = String::new();
+    write_game(game, &LatexFormatter, &mut out);
+    out
+}
+
+impl<'a> fmt::Display for Game<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", write_pgn(self))
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -296,6 +892,14 @@ mod tests {
         assert_eq!(Done(&b""[..], Tag::Event(&b"Tony Rotella"[..])), tag_pair(b"[Event \"Tony Rotella\"]"));
     }
     #[test]
+    fn test_tag_pair_setup_and_fen() {
+        assert_eq!(Done(&b""[..], Tag::SetUp(&b"1"[..])), tag_pair(b"[SetUp \"1\"]"));
+        assert_eq!(
+            Done(&b""[..], Tag::Fen(&b"8/8/8/4k3/8/8/8/4K3 w - - 0 1"[..])),
+            tag_pair(b"[FEN \"8/8/8/4k3/8/8/8/4K3 w - - 0 1\"]")
+        );
+    }
+    #[test]
     fn test_tag_list() {
         assert_eq!(
             Done(&b""[..], vec![Tag::Event(&b"Tony Rotella"[..]), Tag::Date(&b"2017.01.01"[..])]),
@@ -308,6 +912,11 @@ mod tests {
         assert_eq!(Done(&b""[..], &b"this is a\n comment"[..]), commentary_token(b"{this is a\n comment}"));
     }
     #[test]
+    fn test_line_comment() {
+        assert_eq!(Done(&b""[..], &b" this is a comment"[..]), line_comment(b"; this is a comment"));
+        assert_eq!(Done(&b"\n1. e4"[..], &b" eol comment"[..]), line_comment(b"; eol comment\n1. e4"));
+    }
+    #[test]
     fn test_game_result() {
         assert_eq!(Done(&b""[..], Result::WhiteWin), game_result(b"1-0"));
         assert_eq!(Done(&b""[..], Result::BlackWin), game_result(b"0-1"));
@@ -339,12 +948,37 @@ mod tests {
                         san::MoveOrCapture::Capture, SQ_F3,
                         san::Promotion::None,
                         san::Check::None,
-                        san::MoveAnnotation::None
+                        Vec::new()
                     )
                 )
             ),
             game_node(&b"Nxf3"[..])
         );
+        assert_eq!(
+            Done(&b""[..], Node::EscapeComment(&b" clock 1:23:45"[..])),
+            game_node(b"% clock 1:23:45")
+        );
+    }
+    #[test]
+    fn test_game_with_escape_comment_in_movetext() {
+        let result = game(b"[Event \"?\"]
+[Site \"?\"]
+[Date \"????.??.??\"]
+[Round \"?\"]
+[White \"?\"]
+[Black \"?\"]
+[Result \"*\"]
+
+1. e4 e5
+% clock 1:23:45
+2. Nf3 *
+");
+        match result {
+            Done(_, game) => {
+                assert!(game.nodes.contains(&Node::EscapeComment(&b" clock 1:23:45"[..])));
+            },
+            _ => assert!(false, "Unable to parse PGN with an escape comment in the movetext"),
+        }
     }
     #[test]
     fn test_game_node_list() {
@@ -354,7 +988,7 @@ mod tests {
             san::MoveOrCapture::Capture, SQ_F3,
             san::Promotion::None,
             san::Check::None,
-            san::MoveAnnotation::None
+            Vec::new()
         );
         assert_eq!(
             Done(&b""[..], 
@@ -371,6 +1005,125 @@ mod tests {
         );
     }
     #[test]
+    fn test_nest_variations() {
+        let e4 = san::Node::Move(
+            PAWN, san::Source::None, san::MoveOrCapture::Move, SQ_E4,
+            san::Promotion::None, san::Check::None, Vec::new()
+        );
+        let d4 = san::Node::Move(
+            PAWN, san::Source::None, san::MoveOrCapture::Move, SQ_D4,
+            san::Promotion::None, san::Check::None, Vec::new()
+        );
+        let c4 = san::Node::Move(
+            PAWN, san::Source::None, san::MoveOrCapture::Move, SQ_C4,
+            san::Promotion::None, san::Check::None, Vec::new()
+        );
+        let nf6 = san::Node::Move(
+            KNIGHT, san::Source::None, san::MoveOrCapture::Move, SQ_F6,
+            san::Promotion::None, san::Check::None, Vec::new()
+        );
+        let d5 = san::Node::Move(
+            PAWN, san::Source::None, san::MoveOrCapture::Move, SQ_D5,
+            san::Promotion::None, san::Check::None, Vec::new()
+        );
+        let e5 = san::Node::Move(
+            PAWN, san::Source::None, san::MoveOrCapture::Move, SQ_E5,
+            san::Promotion::None, san::Check::None, Vec::new()
+        );
+        let flat = match game_node_list(&b"1. e4 ( 1. d4 ( 1. c4 Nf6 ) d5 ) e5"[..]) {
+            Done(_, nodes) => nodes,
+            _ => { assert!(false, "Unable to parse flat game node list"); return; }
+        };
+        let nested = match nest_variations(flat) {
+            Ok(nested) => nested,
+            Err(e) => { assert!(false, "Unable to nest variations: {:?}", e); return; }
+        };
+        assert_eq!(
+            nested,
+            vec![
+                Node::MoveNumber(1, Periods::One),
+                Node::Move(e4),
+                Node::Variation(vec![
+                    Node::MoveNumber(1, Periods::One),
+                    Node::Move(d4),
+                    Node::Variation(vec![
+                        Node::MoveNumber(1, Periods::One),
+                        Node::Move(c4),
+                        Node::Move(nf6),
+                    ]),
+                    Node::Move(d5),
+                ]),
+                Node::Move(e5),
+            ]
+        );
+    }
+    #[test]
+    fn test_mainline_moves_skips_nested_variation() {
+        let e4 = san::Node::Move(
+            PAWN, san::Source::None, san::MoveOrCapture::Move, SQ_E4,
+            san::Promotion::None, san::Check::None, Vec::new()
+        );
+        let d4 = san::Node::Move(
+            PAWN, san::Source::None, san::MoveOrCapture::Move, SQ_D4,
+            san::Promotion::None, san::Check::None, Vec::new()
+        );
+        let e5 = san::Node::Move(
+            PAWN, san::Source::None, san::MoveOrCapture::Move, SQ_E5,
+            san::Promotion::None, san::Check::None, Vec::new()
+        );
+        let flat = match game_node_list(&b"1. e4 ( 1. d4 ) e5"[..]) {
+            Done(_, nodes) => nodes,
+            _ => { assert!(false, "Unable to parse flat game node list"); return; }
+        };
+        let flat_mainline: Vec<_> = mainline_moves(&flat).cloned().collect();
+        assert_eq!(flat_mainline, vec![e4.clone(), e5.clone()]);
+
+        let nested = nest_variations(flat).unwrap();
+        let nested_mainline: Vec<_> = mainline_moves(&nested).cloned().collect();
+        assert_eq!(nested_mainline, vec![e4, e5]);
+
+        let branch_mainline: Vec<_> = variations(&nested).next().map(|v| mainline_moves(v).cloned().collect()).unwrap();
+        assert_eq!(branch_mainline, vec![d4]);
+    }
+
+    #[test]
+    fn test_game_mainline_and_variations() {
+        let e4 = san::Node::Move(
+            PAWN, san::Source::None, san::MoveOrCapture::Move, SQ_E4,
+            san::Promotion::None, san::Check::None, Vec::new()
+        );
+        let c5 = san::Node::Move(
+            PAWN, san::Source::None, san::MoveOrCapture::Move, SQ_C5,
+            san::Promotion::None, san::Check::None, Vec::new()
+        );
+        let c6 = san::Node::Move(
+            PAWN, san::Source::None, san::MoveOrCapture::Move, SQ_C6,
+            san::Promotion::None, san::Check::None, Vec::new()
+        );
+        let game = Game {
+            escape_comments: vec![],
+            tags: vec![],
+            nodes: vec![
+                Node::MoveNumber(1, Periods::One),
+                Node::Move(e4.clone()),
+                Node::Variation(vec![Node::Move(c6.clone())]),
+                Node::Move(c5.clone()),
+            ],
+            result: Result::Other,
+            starting_position: None,
+        };
+        let mainline: Vec<_> = game.mainline_moves().cloned().collect();
+        assert_eq!(mainline, vec![e4, c5]);
+        let branches: Vec<_> = game.variations().collect();
+        assert_eq!(branches, vec![&[Node::Move(c6)][..]]);
+    }
+
+    #[test]
+    fn test_nest_variations_unbalanced() {
+        assert_eq!(Err(NestingError::UnclosedVariation), nest_variations(vec![Node::StartVariation]));
+        assert_eq!(Err(NestingError::UnexpectedEndVariation), nest_variations(vec![Node::EndVariation]));
+    }
+    #[test]
     fn test_game() {
         let e4 = san::Node::Move(
             PAWN,
@@ -379,7 +1132,7 @@ mod tests {
             SQ_E4,
             san::Promotion::None,
             san::Check::None,
-            san::MoveAnnotation::None
+            Vec::new()
         );
         let c5 = san::Node::Move(
             PAWN,
@@ -388,7 +1141,7 @@ mod tests {
             SQ_C5,
             san::Promotion::None,
             san::Check::None,
-            san::MoveAnnotation::None
+            Vec::new()
         );
         let result = game(&b"% BOOKTITLE = The Killer Sicilian: Fighting 1 e4 with the Kalashnikov
 [Event \"?\"]
@@ -411,6 +1164,13 @@ analyst and openings theoretician, from Ohio, USA.} *
         match result {
             Done(_, game) => {
 
+                assert_eq!(
+                    game.escape_comments,
+                    vec![
+                        &b" BOOKTITLE = The Killer Sicilian: Fighting 1 e4 with the Kalashnikov"[..],
+                        &b" This should be ignored for now"[..],
+                    ]
+                );
                 assert_eq!(game.tags[0], Tag::Event(&b"?"[..]));
                 assert_eq!(game.tags[1], Tag::Site(&b"?"[..]));
                 assert_eq!(game.tags[2], Tag::Date(&b"????.??.??"[..]));
@@ -450,6 +1210,41 @@ analyst and openings theoretician, from Ohio, USA."[..])
         }
     }
 
+    #[test]
+    fn test_game_with_line_comment() {
+        let e4 = san::Node::Move(
+            PAWN,
+            san::Source::None,
+            san::MoveOrCapture::Move,
+            SQ_E4,
+            san::Promotion::None,
+            san::Check::None,
+            Vec::new()
+        );
+        let result = game(&b"[Event \"?\"]
+[Site \"?\"]
+[Date \"????.??.??\"]
+[Round \"?\"]
+[White \"?\"]
+[Black \"?\"]
+[Result \"*\"]
+
+1. e4 ; this opens with the king's pawn
+*
+"[..]);
+        match result {
+            Done(_, game) => {
+                assert_eq!(game.nodes[0], Node::MoveNumber(1, Periods::One));
+                assert_eq!(game.nodes[1], Node::Move(e4));
+                assert_eq!(
+                    game.nodes[2],
+                    Node::Comment(&b" this opens with the king's pawn"[..])
+                );
+                assert_eq!(game.result, Result::Other);
+            },
+            _ => assert!(false, "Unable to parse PGN with a ';' line comment"),
+        }
+    }
     #[test]
     fn test_game_2() {
         let result = game(&b"[Event \"London\"]
@@ -525,7 +1320,8 @@ Rg8 35. Rd1 e3 36. Qc3 Qxd1 37. Rxd1 e2 1-0
                 assert_eq!(game.tags[5], Tag::Black(&b"?"[..]));
                 assert_eq!(game.tags[6], Tag::Result(&b"*"[..]));
                 assert_eq!(game.tags[7], Tag::Other(&b"Annotator"[..], &b"Tony Rotella"[..]));
-                assert_eq!(game.tags[8], Tag::Other(&b"SetUp"[..], &b"1"[..]));
+                assert_eq!(game.tags[8], Tag::SetUp(&b"1"[..]));
+                assert_eq!(game.starting_position, None);
             },
             Error(e) => {
                 println!("Error!: {:?}", e);
@@ -538,6 +1334,35 @@ Rg8 35. Rd1 e3 36. Qc3 Qxd1 37. Rxd1 e2 1-0
         }
     }
     #[test]
+    fn test_game_with_fen_starting_position() {
+        let result = game(&b"[Event \"?\"]
+[Site \"?\"]
+[Date \"????.??.??\"]
+[Round \"?\"]
+[White \"?\"]
+[Black \"?\"]
+[Result \"*\"]
+[SetUp \"1\"]
+[FEN \"8/8/8/4k3/8/8/8/4K3 w - - 0 1\"]
+
+1. Kd5 *
+"[..]);
+        match result {
+            Done(_, game) => {
+                assert_eq!(game.tags[8], Tag::SetUp(&b"1"[..]));
+                assert_eq!(
+                    game.tags[9],
+                    Tag::Fen(&b"8/8/8/4k3/8/8/8/4K3 w - - 0 1"[..])
+                );
+                assert!(game.starting_position.is_some());
+                let nodes = game.starting_position.unwrap();
+                assert!(nodes.contains(&fen::Node::Drop(B_KING)));
+                assert!(nodes.contains(&fen::Node::Move(WHITE)));
+            },
+            _ => assert!(false, "Unable to parse PGN with a FEN starting position"),
+        }
+    }
+    #[test]
     fn test_game_4() {
         let result = game(&b"[Event \"GER/CCM-E/01-C (GER)\"]
 [Site \"ICCF\"]
@@ -580,6 +1405,206 @@ Rf7 16. Ba5 b6 17. cxd6 cxd6 18. Be1 g4 19. Nb4 a6 20. Nc6 Qf8 21. Na3 1/2-1/2"[
         }
     }
 
+    #[test]
+    fn test_write_pgn() {
+        let e4 = san::Node::Move(
+            PAWN, san::Source::None, san::MoveOrCapture::Move, SQ_E4,
+            san::Promotion::None, san::Check::None, Vec::new()
+        );
+        let c5 = san::Node::Move(
+            PAWN, san::Source::None, san::MoveOrCapture::Move, SQ_C5,
+            san::Promotion::None, san::Check::None, Vec::new()
+        );
+        let game = Game {
+            escape_comments: vec![],
+            tags: vec![Tag::White(&b"Fischer, R."[..]), Tag::Result(&b"*"[..])],
+            nodes: vec![
+                Node::MoveNumber(1, Periods::One),
+                Node::Move(e4),
+                Node::Move(c5),
+            ],
+            result: Result::Other,
+            starting_position: None,
+        };
+        let text = write_pgn(&game);
+        assert!(text.contains("[White \"Fischer, R.\"]\n"));
+        assert!(text.contains("[Result \"*\"]\n"));
+        assert!(text.contains("1. e4 c5 *"));
+        assert_eq!(text, game.to_string());
+    }
+
+    #[test]
+    fn test_write_pgn_with_variation() {
+        let e4 = san::Node::Move(
+            PAWN, san::Source::None, san::MoveOrCapture::Move, SQ_E4,
+            san::Promotion::None, san::Check::None, Vec::new()
+        );
+        let d4 = san::Node::Move(
+            PAWN, san::Source::None, san::MoveOrCapture::Move, SQ_D4,
+            san::Promotion::None, san::Check::None, Vec::new()
+        );
+        let game = Game {
+            escape_comments: vec![],
+            tags: vec![Tag::Result(&b"*"[..])],
+            nodes: vec![
+                Node::MoveNumber(1, Periods::One),
+                Node::Move(e4),
+                Node::Variation(vec![
+                    Node::MoveNumber(1, Periods::One),
+                    Node::Move(d4),
+                ]),
+            ],
+            result: Result::Other,
+            starting_position: None,
+        };
+        let text = write_pgn(&game);
+        assert!(text.contains("1. e4 (1. d4) *"));
+    }
+
+    #[test]
+    fn test_write_latex() {
+        let e4 = san::Node::Move(
+            PAWN, san::Source::None, san::MoveOrCapture::Move, SQ_E4,
+            san::Promotion::None, san::Check::None, Vec::new()
+        );
+        let game = Game {
+            escape_comments: vec![],
+            tags: vec![],
+            nodes: vec![
+                Node::MoveNumber(1, Periods::One),
+                Node::Move(e4),
+                Node::Comment(&b"the king's pawn"[..]),
+            ],
+            result: Result::Other,
+            starting_position: None,
+        };
+        let text = write_latex(&game);
+        assert!(text.contains("\\textbf{e4}"));
+        assert!(text.contains("\\textit{the king's pawn}"));
+    }
+
+    #[test]
+    fn test_write_latex_nag_closes_its_math_group() {
+        let e4 = san::Node::Move(
+            PAWN, san::Source::None, san::MoveOrCapture::Move, SQ_E4,
+            san::Promotion::None, san::Check::None, Vec::new()
+        );
+        let game = Game {
+            escape_comments: vec![],
+            tags: vec![],
+            nodes: vec![
+                Node::MoveNumber(1, Periods::One),
+                Node::Move(e4),
+                Node::Nag(NumericAnnotationGlyph(1)),
+            ],
+            result: Result::Other,
+            starting_position: None,
+        };
+        let text = write_latex(&game);
+        assert!(text.contains("\\textsuperscript{$1$}"));
+        assert_eq!(text.matches('$').count() % 2, 0);
+    }
+
+    #[test]
+    fn test_annotation_from_nag() {
+        assert_eq!(Annotation::Good, Annotation::from_nag(1));
+        assert_eq!(Annotation::Blunder, Annotation::from_nag(4));
+        assert_eq!(Annotation::Unclear, Annotation::from_nag(13));
+        assert_eq!(Annotation::WhiteSlightAdvantage, Annotation::from_nag(14));
+        assert_eq!(Annotation::BlackSlightAdvantage, Annotation::from_nag(15));
+        assert_eq!(Annotation::Other(123), Annotation::from_nag(123));
+    }
+
+    #[test]
+    fn test_annotation_round_trips_through_nag() {
+        for n in 0u64..20 {
+            assert_eq!(n, Annotation::from_nag(n).to_nag());
+        }
+        assert_eq!(123, Annotation::from_nag(123).to_nag());
+    }
+
+    #[test]
+    fn test_nag_glyph_annotation() {
+        assert_eq!(Annotation::Brilliant, NumericAnnotationGlyph(3).annotation());
+        assert_eq!(NumericAnnotationGlyph(3), NumericAnnotationGlyph::from(Annotation::Brilliant));
+    }
+
+    #[test]
+    fn test_games_streams_each_game() {
+        let database = b"[Event \"One\"]
+[Site \"?\"]
+[Date \"????.??.??\"]
+[Round \"?\"]
+[White \"?\"]
+[Black \"?\"]
+[Result \"*\"]
+
+1. e4 *
+
+[Event \"Two\"]
+[Site \"?\"]
+[Date \"????.??.??\"]
+[Round \"?\"]
+[White \"?\"]
+[Black \"?\"]
+[Result \"1-0\"]
+
+1. d4 1-0
+";
+        let results: Vec<_> = games(&database[..]).collect();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].as_ref().unwrap().tags[0], Tag::Event(&b"One"[..]));
+        assert_eq!(results[0].as_ref().unwrap().result, Result::Other);
+        assert_eq!(results[1].as_ref().unwrap().tags[0], Tag::Event(&b"Two"[..]));
+        assert_eq!(results[1].as_ref().unwrap().result, Result::WhiteWin);
+    }
+
+    #[test]
+    fn test_games_reports_offset_of_failed_game() {
+        let database = b"[Event \"One\"]
+[Site \"?\"]
+[Date \"????.??.??\"]
+[Round \"?\"]
+[White \"?\"]
+[Black \"?\"]
+[Result \"*\"]
+
+1. e4 *
+
+this is not a game
+";
+        let mut iter = games(&database[..]);
+        assert!(iter.next().unwrap().is_ok());
+        match iter.next() {
+            Some(Err(ParseError{ offset })) => assert_eq!(offset, 104),
+            other => assert!(false, "Expected a ParseError, got {:?}", other),
+        }
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn test_games_empty_input() {
+        assert_eq!(games(&b""[..]).next(), None);
+        assert_eq!(games(&b"   \n\t  "[..]).next(), None);
+    }
+
+    #[test]
+    fn test_pgn_collects_all_games() {
+        let database = b"[Event \"One\"]
+[Site \"?\"]
+[Date \"????.??.??\"]
+[Round \"?\"]
+[White \"?\"]
+[Black \"?\"]
+[Result \"*\"]
+
+1. e4 *
+";
+        let parsed = pgn(&database[..]);
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].tags[0], Tag::Event(&b"One"[..]));
+    }
+
     #[bench]
     fn bench_parse_game(b: &mut Bencher) {
         b.iter(|| {
@@ -20,6 +20,12 @@
 //------------------------------------------------------------------------------
 
 use super::super::types::*;
+use super::super::bitboard;
+use super::super::position::Position;
+
+use std::str;
+use std::str::FromStr;
+use nom::IResult;
 
 ///-----------------------------------------------------------------------------
 #[derive(Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Debug, Hash)]
@@ -50,24 +56,19 @@ pub enum Check {
 }
 
 ///-----------------------------------------------------------------------------
-#[derive(Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Debug, Hash)]
-pub enum MoveAnnotation {
-    None,
-    Strong,
-    Brilliant,
-    Mistake,
-    Blunder,
-    Interesting,
-    Dubious
-}
-
-///-----------------------------------------------------------------------------
-#[derive(Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Debug, Hash)]
+/// The purely syntactic parse of one SAN move: piece type, optional
+/// disambiguation, capture flag, destination square, promotion piece,
+/// check/mate suffix, and any Numeric Annotation Glyphs attached to it --
+/// everything `san_move` can read off the text alone. Turning this into a
+/// concrete `types::Move` needs board context (to find the matching piece
+/// and rule out illegal/ambiguous candidates), so that resolution is a
+/// separate step against a `Position`.
+#[derive(Clone, PartialEq, PartialOrd, Eq, Ord, Debug, Hash)]
 pub enum Node {
-    Move(PieceType, Source, MoveOrCapture, Square, Promotion, Check, MoveAnnotation),
-    CastleKingSide(Check, MoveAnnotation),
-    CastleQueenSide(Check, MoveAnnotation),
-    NullMove(Check, MoveAnnotation),
+    Move(PieceType, Source, MoveOrCapture, Square, Promotion, Check, Vec<u8>),
+    CastleKingSide(Check, Vec<u8>),
+    CastleQueenSide(Check, Vec<u8>),
+    NullMove(Check, Vec<u8>),
     InvalidMove
 }
 
@@ -83,7 +84,7 @@ named!(pub san_piece<PieceType>,
                 'R' => ROOK,
                 'Q' => QUEEN,
                 'K' => KING,
-                _ => PIECE_TYPE_NB // This should never happen because of above.
+                _ => unreachable!("one_of! above only matches PNBRQKpnbrqk")
             }
         }
     )
@@ -102,7 +103,7 @@ named!(pub san_file<File>,
                 'f' | 'F' => FILE_F,
                 'g' | 'G' => FILE_G,
                 'h' | 'H' => FILE_H,
-                _ => FILE_NB // This should never happen because of above.
+                _ => unreachable!("one_of! above only matches abcdefghABCDEFGH")
             }
         }
     )
@@ -122,7 +123,7 @@ named!(pub san_rank<Rank>,
                 '6' => RANK_6,
                 '7' => RANK_7,
                 '8' => RANK_8,
-                _ => RANK_NB // This should never happen because of above.
+                _ => unreachable!("one_of! above only matches 12345678")
             }
         }
     )
@@ -160,23 +161,42 @@ named!(pub san_check<Check>,
 );
 
 ///-----------------------------------------------------------------------------
-named!(pub san_move_annotation<MoveAnnotation>,
+/// The traditional suffix annotations (`!`, `?`, `!!`, `??`, `!?`, `?!`),
+/// mapped onto the Numeric Annotation Glyph each one stands for (PGN spec,
+/// appendix A) so both spellings unify into the same value.
+named!(pub san_move_annotation<u8>,
     map!(
         alt_complete!(tag!("!!") | tag!("??") | tag!("?!") | tag!("!?") | tag!("!") | tag!("?")),
         |suffix: &[u8]| {
             match suffix {
-                b"!!" => MoveAnnotation::Brilliant,
-                b"!" => MoveAnnotation::Strong,
-                b"??" => MoveAnnotation::Blunder,
-                b"?" => MoveAnnotation::Mistake,
-                b"!?" => MoveAnnotation::Interesting,
-                b"?!" => MoveAnnotation::Dubious,
-                _ => MoveAnnotation::None
+                b"!" => 1,
+                b"?" => 2,
+                b"!!" => 3,
+                b"??" => 4,
+                b"!?" => 5,
+                b"?!" => 6,
+                _ => 0
             }
         }
     )
 );
 
+///-----------------------------------------------------------------------------
+/// A Numeric Annotation Glyph: `$` followed by an integer 0-255 (PGN spec,
+/// appendix A covers codes well beyond move quality -- positional
+/// assessments, time-trouble markers, and so on).
+named!(pub san_nag<u8>,
+    map_res!(map_res!(preceded!(char!('$'), digit), str::from_utf8), FromStr::from_str)
+);
+
+///-----------------------------------------------------------------------------
+/// Every annotation written directly after a move, in either spelling, in
+/// the order they appear. A move can carry several (`e4!$16`), so this
+/// never fails -- it just yields an empty `Vec` when there are none.
+/// Whitespace ends the list: a glyph set off by a space is left unread
+/// (PGN movetext reads it as a separate `Nag` node).
+named!(pub san_annotations<Vec<u8> >, many0!(complete!(alt_complete!(san_nag | san_move_annotation))));
+
 ///-----------------------------------------------------------------------------
 named!(pub san_square<Square>, 
     do_parse!(
@@ -204,7 +224,7 @@ named!(pub san_pawn_move_bare<Node>,
                 MoveOrCapture::Move,
                 square,
                 Promotion::None,
-                Check::None, MoveAnnotation::None
+                Check::None, Vec::new()
             )
         }
     )
@@ -227,7 +247,7 @@ named!(pub san_pawn_capture_bare<Node>,
                 capture,
                 square,
                 Promotion::None,
-                Check::None, MoveAnnotation::None
+                Check::None, Vec::new()
             )
         }
     )
@@ -241,17 +261,16 @@ named!(pub san_pawn_move<Node>,
             promotion: opt!(complete!(san_promotion)) >>
             promotion_piece: opt!(complete!(san_piece)) >>
             check: opt!(complete!(san_check)) >>
-            annotation: opt!(complete!(san_move_annotation)) >>
-            (square, promotion, promotion_piece, check, annotation)
+            annotations: san_annotations >>
+            (square, promotion, promotion_piece, check, annotations)
         ),
-        |(square, promotion, promotion_piece, check, annotation)| {
+        |(square, promotion, promotion_piece, check, annotations)| {
             let check = if let Some(x) = check { x } else { Check::None };
-            let annotation = if let Some(x) = annotation { x } else { MoveAnnotation::None };
             let promotion = match (promotion, promotion_piece) {
                 (Some(_), Some(promotion_piece)) => Promotion::PieceType(promotion_piece),
                 _ => Promotion::None
             };
-            Node::Move(PAWN, Source::None, MoveOrCapture::Move, square, promotion, check, annotation)
+            Node::Move(PAWN, Source::None, MoveOrCapture::Move, square, promotion, check, annotations)
         }
     )
 );
@@ -266,18 +285,17 @@ named!(pub san_pawn_capture<Node>,
             promotion: opt!(complete!(san_promotion)) >>
             promotion_piece: opt!(complete!(san_piece)) >>
             check: opt!(complete!(san_check)) >>
-            annotation: opt!(complete!(san_move_annotation)) >>
-            (file, capture, square, promotion, promotion_piece, check, annotation)
+            annotations: san_annotations >>
+            (file, capture, square, promotion, promotion_piece, check, annotations)
         ),
-        |(file, capture, square, promotion, promotion_piece, check, annotation)| {
+        |(file, capture, square, promotion, promotion_piece, check, annotations)| {
             let source = Source::File(file);
             let check = if let Some(x) = check { x } else { Check::None };
-            let annotation = if let Some(x) = annotation { x } else { MoveAnnotation::None };
             let promotion = match (promotion, promotion_piece) {
                 (Some(_), Some(promotion_piece)) => Promotion::PieceType(promotion_piece),
                 _ => Promotion::None
             };
-            Node::Move(PAWN, source, capture, square, promotion, check, annotation)
+            Node::Move(PAWN, source, capture, square, promotion, check, annotations)
         }
     )
 );
@@ -292,7 +310,7 @@ named!(pub san_piece_move_bare<Node>,
             (piece, square)
         ),
         |(piece, square)| {
-            Node::Move(piece, Source::None, MoveOrCapture::Move, square, Promotion::None, Check::None, MoveAnnotation::None)
+            Node::Move(piece, Source::None, MoveOrCapture::Move, square, Promotion::None, Check::None, Vec::new())
         }
     )
 );
@@ -307,28 +325,27 @@ named!(pub san_piece_move<Node>,
             capture: opt!(complete!(san_capture)) >>
             square: opt!(complete!(san_square)) >>
             check: opt!(complete!(san_check)) >>
-            annotation: opt!(complete!(san_move_annotation)) >>
-            (piece, file, rank, capture, square, check, annotation)
+            annotations: san_annotations >>
+            (piece, file, rank, capture, square, check, annotations)
         ),
-        |(piece, file, rank, capture, square, check, annotation)| {
+        |(piece, file, rank, capture, square, check, annotations)| {
             let capture = if let Some(x) = capture { x } else { MoveOrCapture::Move };
             let check = if let Some(x) = check { x } else { Check::None };
-            let annotation = if let Some(x) = annotation { x } else { MoveAnnotation::None };
             match (file, rank, square) {
                 (Some(f), Some(r), None) => {
-                    Node::Move(piece, Source::None, capture, make_square(f, r), Promotion::None, check, annotation)
+                    Node::Move(piece, Source::None, capture, make_square(f, r), Promotion::None, check, annotations)
                 },
                 (None, None, Some(square)) => {
-                    Node::Move(piece, Source::None, capture, square, Promotion::None, check, annotation)
+                    Node::Move(piece, Source::None, capture, square, Promotion::None, check, annotations)
                 },
                 (Some(f), None, Some(square)) => {
-                    Node::Move(piece, Source::File(f), capture, square, Promotion::None, check, annotation)
+                    Node::Move(piece, Source::File(f), capture, square, Promotion::None, check, annotations)
                 },
                 (None, Some(r), Some(square)) => {
-                    Node::Move(piece, Source::Rank(r), capture, square, Promotion::None, check, annotation)
+                    Node::Move(piece, Source::Rank(r), capture, square, Promotion::None, check, annotations)
                 },
                 (Some(f), Some(r), Some(square)) => {
-                    Node::Move(piece, Source::Square(make_square(f, r)), capture, square, Promotion::None, check, annotation)
+                    Node::Move(piece, Source::Square(make_square(f, r)), capture, square, Promotion::None, check, annotations)
                 },
                 _ => Node::InvalidMove
             }
@@ -354,13 +371,12 @@ named!(pub san_null_move<Node>,
         do_parse!(
             alt_complete!(tag!("--") | tag!("Z0") | tag!("z0")) >>
             check: opt!(complete!(san_check)) >>
-            annotation: opt!(complete!(san_move_annotation)) >>
-            (check, annotation)
+            annotations: san_annotations >>
+            (check, annotations)
         ),
-        |(check, annotation)| {
+        |(check, annotations)| {
             let check = if let Some(x) = check { x } else { Check::None };
-            let annotation = if let Some(x) = annotation { x } else { MoveAnnotation::None };
-            Node::NullMove(check, annotation)
+            Node::NullMove(check, annotations)
         }
     )
 );
@@ -371,13 +387,12 @@ named!(pub san_castle_king_side<Node>,
         do_parse!(
             tag!("O-O") >>
             check: opt!(complete!(san_check)) >>
-            annotation: opt!(complete!(san_move_annotation)) >>
-            (check, annotation)
+            annotations: san_annotations >>
+            (check, annotations)
         ),
-        |(check, annotation)| {
+        |(check, annotations)| {
             let check = if let Some(x) = check { x } else { Check::None };
-            let annotation = if let Some(x) = annotation { x } else { MoveAnnotation::None };
-            Node::CastleKingSide(check, annotation)
+            Node::CastleKingSide(check, annotations)
         }
     )
 );
@@ -388,13 +403,12 @@ named!(pub san_castle_queen_side<Node>,
         do_parse!(
             tag!("O-O-O") >>
             check: opt!(complete!(san_check)) >>
-            annotation: opt!(complete!(san_move_annotation)) >>
-            (check, annotation)
+            annotations: san_annotations >>
+            (check, annotations)
         ),
-        |(check, annotation)| {
+        |(check, annotations)| {
             let check = if let Some(x) = check { x } else { Check::None };
-            let annotation = if let Some(x) = annotation { x } else { MoveAnnotation::None };
-            Node::CastleQueenSide(check, annotation)
+            Node::CastleQueenSide(check, annotations)
         }
     )
 );
@@ -408,6 +422,473 @@ named!(pub san_move<Node>, alt_complete!(
     san_null_move
 ));
 
+///-----------------------------------------------------------------------------
+/// Long algebraic (UCI) notation: an origin square and a destination
+/// square, with an optional trailing promotion piece -- `e2e4`, `e7e8q`,
+/// or `0000` for a null move (`square` already reads `0` as the sentinel
+/// `SQUARE_NB`, so a pair of them falls out of reusing it here without a
+/// special case). UCI text doesn't mark captures, check, or castling --
+/// a castle is just the king moving to its rook's square, or two squares
+/// over -- so the resulting `Node::Move` always carries
+/// `MoveOrCapture::Move` and `Check::None`, and its `PieceType` is
+/// `NO_PIECE_TYPE` since UCI never says which piece is moving. A caller
+/// holding the `Position` the move is played from should read the real
+/// piece off `pos.piece_on(from)` before handing the node to
+/// `resolve_san`.
+named!(pub uci_move<Node>,
+    do_parse!(
+        from: super::square >>
+        to: super::square >>
+        promotion: opt!(complete!(super::piece)) >>
+        (match (from, to) {
+            (SQUARE_NB, _) | (_, SQUARE_NB) => Node::NullMove(Check::None, Vec::new()),
+            _ => {
+                let promotion = match promotion {
+                    Some(p) => Promotion::PieceType(p),
+                    None => Promotion::None
+                };
+                Node::Move(NO_PIECE_TYPE, Source::Square(from), MoveOrCapture::Move, to, promotion, Check::None, Vec::new())
+            }
+        })
+    )
+);
+
+///-----------------------------------------------------------------------------
+/// Parse `input` as SAN, falling back to long algebraic (UCI) notation if
+/// that fails, so tools ingesting mixed move notation just work. Both
+/// require the whole input to be consumed; if neither does, the SAN
+/// error is the one reported, since SAN is the richer and more common
+/// spelling.
+pub fn parse_san_or_uci(input: &[u8]) -> Result<Node, SanParseError> {
+    match parse_san(input) {
+        Ok(node) => Ok(node),
+        Err(san_err) => match uci_move(input) {
+            IResult::Done(rest, node) if rest.is_empty() => Ok(node),
+            _ => Err(san_err)
+        }
+    }
+}
+
+///-----------------------------------------------------------------------------
+/// Why `parse_san` couldn't turn raw text into a `Node`. This is about the
+/// shape of the text itself; whether the move it describes is actually
+/// legal in some position is `resolve_san`'s `SanError` instead.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum SanParseError {
+    /// The input had nothing in it.
+    EmptyInput,
+    /// `san_move` matched a move but didn't consume the whole input.
+    UnexpectedTrailing(Vec<u8>),
+    /// Nothing resembling a move, a castle, or a null move.
+    NotAMove,
+    /// A `=` promotion marker with no piece letter after it, e.g. `e8=`.
+    /// `san_pawn_move`/`san_pawn_capture` otherwise parse this as a plain
+    /// non-promoting move and silently drop the `=`.
+    DanglingPromotionMarker,
+}
+
+///-----------------------------------------------------------------------------
+fn dangling_promotion_marker(input: &[u8]) -> bool {
+    for (i, &b) in input.iter().enumerate() {
+        if b == b'=' {
+            match input.get(i + 1) {
+                Some(&p) if b"PNBRQKpnbrqk".contains(&p) => {},
+                _ => return true
+            }
+        }
+    }
+    false
+}
+
+///-----------------------------------------------------------------------------
+/// Parse SAN text into a `Node`, requiring the whole input to be
+/// consumed. The conventional string-parsing entry point; `FromStr` just
+/// delegates here.
+pub fn parse_san(input: &[u8]) -> Result<Node, SanParseError> {
+    if input.is_empty() {
+        return Err(SanParseError::EmptyInput);
+    }
+    if dangling_promotion_marker(input) {
+        return Err(SanParseError::DanglingPromotionMarker);
+    }
+    match san_move(input) {
+        IResult::Done(_, Node::InvalidMove) => Err(SanParseError::NotAMove),
+        IResult::Done(rest, node) => {
+            if rest.is_empty() {
+                Ok(node)
+            } else {
+                Err(SanParseError::UnexpectedTrailing(rest.to_vec()))
+            }
+        },
+        _ => Err(SanParseError::NotAMove)
+    }
+}
+
+///-----------------------------------------------------------------------------
+impl FromStr for Node {
+    type Err = SanParseError;
+
+    fn from_str(s: &str) -> Result<Node, SanParseError> {
+        parse_san(s.as_bytes())
+    }
+}
+
+///-----------------------------------------------------------------------------
+fn file_char(f: File) -> char { (b'a' + f.0 as u8) as char }
+
+///-----------------------------------------------------------------------------
+fn rank_char(r: Rank) -> char { (b'1' + r.0 as u8) as char }
+
+///-----------------------------------------------------------------------------
+fn piece_char(pt: PieceType) -> char {
+    match pt {
+        KNIGHT => 'N',
+        BISHOP => 'B',
+        ROOK => 'R',
+        QUEEN => 'Q',
+        KING => 'K',
+        _ => ' ' // Pawns have no letter in SAN.
+    }
+}
+
+///-----------------------------------------------------------------------------
+fn write_square(sq: Square, out: &mut String) {
+    out.push(file_char(file_of(sq)));
+    out.push(rank_char(rank_of(sq)));
+}
+
+///-----------------------------------------------------------------------------
+fn write_check(check: Check, out: &mut String) {
+    match check {
+        Check::Check => out.push('+'),
+        Check::Checkmate => out.push('#'),
+        Check::None => {}
+    }
+}
+
+///-----------------------------------------------------------------------------
+/// Render a move's Numeric Annotation Glyphs back to their canonical `$n`
+/// text, space-separated from whatever precedes them (the move itself, or
+/// an earlier glyph).
+fn write_nags(nags: &[u8], out: &mut String) {
+    for nag in nags {
+        out.push(' ');
+        out.push('$');
+        out.push_str(&nag.to_string());
+    }
+}
+
+///-----------------------------------------------------------------------------
+/// Render a parsed `Node` back to its SAN text. This is the inverse of
+/// `san_move` for a node without glyphs: since `Node` already carries the
+/// disambiguation, capture, promotion and check/annotation flags as parsed,
+/// printing it back out needs no board context at all. Glyphs are written
+/// space-separated (`e8=Q# $3`), which `san_move` leaves unread.
+pub fn write_san_move(node: &Node, out: &mut String) {
+    match *node {
+        Node::Move(piece, source, capture, square, promotion, check, ref nags) => {
+            if piece == PAWN {
+                if let Source::File(f) = source {
+                    out.push(file_char(f));
+                }
+            } else {
+                out.push(piece_char(piece));
+                match source {
+                    Source::File(f) => out.push(file_char(f)),
+                    Source::Rank(r) => out.push(rank_char(r)),
+                    Source::Square(sq) => write_square(sq, out),
+                    Source::None => {}
+                }
+            }
+            if capture == MoveOrCapture::Capture {
+                out.push('x');
+            }
+            write_square(square, out);
+            if let Promotion::PieceType(pt) = promotion {
+                out.push('=');
+                out.push(piece_char(pt));
+            }
+            write_check(check, out);
+            write_nags(nags, out);
+        },
+        Node::CastleKingSide(check, ref nags) => {
+            out.push_str("O-O");
+            write_check(check, out);
+            write_nags(nags, out);
+        },
+        Node::CastleQueenSide(check, ref nags) => {
+            out.push_str("O-O-O");
+            write_check(check, out);
+            write_nags(nags, out);
+        },
+        Node::NullMove(check, ref nags) => {
+            out.push_str("--");
+            write_check(check, out);
+            write_nags(nags, out);
+        },
+        Node::InvalidMove => {}
+    }
+}
+
+///-----------------------------------------------------------------------------
+/// A `Node` resolved against a `Position`: the concrete `types::Move` it
+/// refers to, plus which piece type makes it (so a caller doesn't have
+/// to decode `mv` again to tell a king move from a pawn push).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ResolvedMove {
+    pub mv: Move,
+    pub piece: PieceType,
+}
+
+///-----------------------------------------------------------------------------
+/// Why `resolve_san` couldn't turn a `Node` into a concrete move against
+/// `pos`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SanError {
+    /// `Node::InvalidMove` can never resolve to anything.
+    InvalidMove,
+    /// No candidate piece can legally make this move.
+    Illegal,
+    /// More than one candidate piece can legally make this move, and the
+    /// `Source` disambiguation in the SAN text didn't narrow it down.
+    Ambiguous,
+    /// A promotion piece was given for a move that doesn't reach the
+    /// last rank, or omitted for one that does.
+    BadPromotion,
+}
+
+///-----------------------------------------------------------------------------
+/// Resolve a syntactic `Node` into a concrete, legal `types::Move`
+/// against `pos`, modeled on the `chess` crate's `ChessMove::from_san`:
+/// enumerate every piece of the right type belonging to the side to move
+/// that can pseudo-legally reach the target square, narrow that set by
+/// the SAN text's `Source` disambiguation, then drop any candidate that
+/// would leave its own king in check. Exactly one survivor resolves;
+/// zero candidates is `SanError::Illegal`, more than one is
+/// `SanError::Ambiguous`.
+pub fn resolve_san(node: Node, pos: &Position) -> Result<ResolvedMove, SanError> {
+    match node {
+        Node::InvalidMove => Err(SanError::InvalidMove),
+        Node::NullMove(_, _) => Ok(ResolvedMove { mv: MOVE_NULL, piece: NO_PIECE_TYPE }),
+        Node::CastleKingSide(_, _) => resolve_castle(pos, KING_SIDE),
+        Node::CastleQueenSide(_, _) => resolve_castle(pos, QUEEN_SIDE),
+        Node::Move(piece, source, capture, target, promotion, _, _) => {
+            resolve_piece_move(pos, piece, source, capture, target, promotion)
+        }
+    }
+}
+
+///-----------------------------------------------------------------------------
+fn resolve_castle(pos: &Position, side: CastlingSide) -> Result<ResolvedMove, SanError> {
+    let us = pos.side_to_move();
+    let cr = make_castling(us, side);
+    if !pos.can_castle(cr) {
+        return Err(SanError::Illegal);
+    }
+    if (pos.castling_path(cr) & pos.occupied()) != Bitboard(0) {
+        return Err(SanError::Illegal);
+    }
+
+    let king_square = pos.king_square(us);
+    let king_to = castling_king_target(cr);
+    let them = -us;
+    let step: i8 = if king_to > king_square { 1 } else { -1 };
+    let mut sq = king_square;
+    loop {
+        if pos.is_attacked_by(sq, them) {
+            return Err(SanError::Illegal);
+        }
+        if sq == king_to {
+            break;
+        }
+        sq = Square(sq.0 + step);
+    }
+
+    Ok(ResolvedMove { mv: make_move_castling(king_square, pos.castling_rook_square(cr)), piece: KING })
+}
+
+///-----------------------------------------------------------------------------
+fn resolve_piece_move(
+    pos: &Position,
+    piece: PieceType,
+    source: Source,
+    capture: MoveOrCapture,
+    target: Square,
+    promotion: Promotion,
+) -> Result<ResolvedMove, SanError> {
+    let us = pos.side_to_move();
+
+    if piece == PAWN {
+        let last_rank = relative_rank(us, RANK_8);
+        match (rank_of(target) == last_rank, promotion) {
+            (true, Promotion::None) | (false, Promotion::PieceType(_)) => return Err(SanError::BadPromotion),
+            _ => {}
+        }
+    } else if promotion != Promotion::None {
+        return Err(SanError::BadPromotion);
+    }
+
+    let en_passant = piece == PAWN
+        && capture == MoveOrCapture::Capture
+        && target == pos.ep_square()
+        && pos.piece_on(target) == NO_PIECE;
+
+    // The capture flag must agree with what's actually on the target
+    // square -- a plain-move node can't land on an occupied square and a
+    // capture node (barring en passant, already handled above) needs an
+    // enemy piece to take.
+    if !en_passant {
+        let occupant = pos.piece_on(target);
+        let consistent = match capture {
+            MoveOrCapture::Capture => occupant != NO_PIECE && color_of(occupant) != us,
+            MoveOrCapture::Move => occupant == NO_PIECE,
+        };
+        if !consistent {
+            return Err(SanError::Illegal);
+        }
+    }
+
+    let mut candidates = Vec::new();
+    for from in pos.pieces(us, piece) {
+        if !source_matches(source, from) {
+            continue;
+        }
+        let reaches = if piece == PAWN {
+            if capture == MoveOrCapture::Capture {
+                (bitboard::pawn_captures(us, from) & target) != Bitboard(0)
+            } else {
+                (bitboard::pawn_pushes(us, from, pos.occupied()) & target) != Bitboard(0)
+            }
+        } else {
+            (piece_attacks(piece, from, pos.occupied()) & target) != Bitboard(0)
+        };
+        if !reaches {
+            continue;
+        }
+
+        let mv = if en_passant {
+            make_move_enpassant(from, target)
+        } else if let Promotion::PieceType(pt) = promotion {
+            make_move_promotion(from, target, pt)
+        } else {
+            make_move_simple(from, target)
+        };
+        if pos.leaves_king_in_check(us, mv) {
+            continue;
+        }
+        candidates.push(mv);
+    }
+
+    match candidates.len() {
+        0 => Err(SanError::Illegal),
+        1 => Ok(ResolvedMove { mv: candidates[0], piece: piece }),
+        _ => Err(SanError::Ambiguous),
+    }
+}
+
+///-----------------------------------------------------------------------------
+fn source_matches(source: Source, from: Square) -> bool {
+    match source {
+        Source::None => true,
+        Source::File(f) => file_of(from) == f,
+        Source::Rank(r) => rank_of(from) == r,
+        Source::Square(sq) => from == sq,
+    }
+}
+
+///-----------------------------------------------------------------------------
+fn piece_attacks(piece: PieceType, from: Square, occupied: Bitboard) -> Bitboard {
+    match piece {
+        KNIGHT => bitboard::knight_attacks_from(from),
+        KING => bitboard::king_attacks_from(from),
+        BISHOP | ROOK | QUEEN => bitboard::attacks_bb(piece, from, occupied),
+        _ => Bitboard(0),
+    }
+}
+
+///-----------------------------------------------------------------------------
+/// Encode `mv` as the shortest SAN string that round-trips back to it
+/// through `san_move`/`resolve_san`, mirroring the `chess` crate's
+/// `Display` impl for `ChessMove`: disambiguation is minimized (file,
+/// then rank, then the full origin square) by checking which other
+/// pieces of the same type could also reach the destination, and the
+/// check/checkmate suffix is read off `pos` by actually probing whether
+/// `mv` attacks the opponent's king and whether they have any reply.
+pub fn encode_san(mv: Move, pos: &Position) -> String {
+    let mut out = String::new();
+    if mv == MOVE_NULL {
+        out.push_str("--");
+        return out;
+    }
+
+    let from = from_square(mv);
+    let to = to_square(mv);
+    let move_type = type_of_move(mv);
+
+    if move_type == CASTLING {
+        out.push_str(if to > from { "O-O" } else { "O-O-O" });
+    } else {
+        let piece = type_of_piece(pos.piece_on(from));
+        let is_capture = move_type == ENPASSANT || pos.piece_on(to) != NO_PIECE;
+
+        if piece == PAWN {
+            if is_capture {
+                out.push(file_char(file_of(from)));
+            }
+        } else {
+            out.push(piece_char(piece));
+            out.push_str(&disambiguation(pos, piece, from, to));
+        }
+        if is_capture {
+            out.push('x');
+        }
+        write_square(to, &mut out);
+        if move_type == PROMOTION {
+            out.push('=');
+            out.push(piece_char(promotion_type(mv)));
+        }
+    }
+
+    if pos.move_gives_check(mv) {
+        out.push(if pos.move_gives_checkmate(mv) { '#' } else { '+' });
+    }
+    out
+}
+
+///-----------------------------------------------------------------------------
+/// The minimal `Source` text needed to single `from` out among every
+/// other `piece` belonging to the side to move that could also legally
+/// land on `to`: nothing if `from` is the only one, else the origin
+/// file if that alone is unique among the candidates, else the rank, else
+/// the full square.
+fn disambiguation(pos: &Position, piece: PieceType, from: Square, to: Square) -> String {
+    let us = pos.side_to_move();
+    let occupied = pos.occupied();
+    let mut others = Vec::new();
+    for sq in pos.pieces(us, piece) {
+        if sq == from {
+            continue;
+        }
+        let reaches = (piece_attacks(piece, sq, occupied) & to) != Bitboard(0);
+        if reaches && !pos.leaves_king_in_check(us, make_move_simple(sq, to)) {
+            others.push(sq);
+        }
+    }
+
+    if others.is_empty() {
+        return String::new();
+    }
+    if others.iter().all(|&sq| file_of(sq) != file_of(from)) {
+        return file_char(file_of(from)).to_string();
+    }
+    if others.iter().all(|&sq| rank_of(sq) != rank_of(from)) {
+        return rank_char(rank_of(from)).to_string();
+    }
+    let mut out = String::new();
+    write_square(from, &mut out);
+    out
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -467,65 +948,341 @@ mod tests {
         assert_eq!(Done(&b""[..], SQ_E4), san_square(&b"e4"[..]));
     }
     #[test]
+    fn test_write_san_move() {
+        let mut out = String::new();
+        write_san_move(&Node::Move(PAWN, Source::None, MoveOrCapture::Move, SQ_E4, Promotion::None, Check::None, Vec::new()), &mut out);
+        assert_eq!("e4", out);
+
+        out.clear();
+        write_san_move(&Node::Move(PAWN, Source::File(FILE_D), MoveOrCapture::Capture, SQ_E4, Promotion::None, Check::None, Vec::new()), &mut out);
+        assert_eq!("dxe4", out);
+
+        out.clear();
+        write_san_move(&Node::Move(KNIGHT, Source::File(FILE_E), MoveOrCapture::Capture, SQ_F3, Promotion::None, Check::Check, Vec::new()), &mut out);
+        assert_eq!("Nexf3+", out);
+
+        out.clear();
+        write_san_move(&Node::Move(PAWN, Source::None, MoveOrCapture::Move, SQ_E8, Promotion::PieceType(QUEEN), Check::Checkmate, vec![3]), &mut out);
+        assert_eq!("e8=Q# $3", out);
+
+        out.clear();
+        write_san_move(&Node::CastleKingSide(Check::None, Vec::new()), &mut out);
+        assert_eq!("O-O", out);
+
+        out.clear();
+        write_san_move(&Node::CastleQueenSide(Check::Check, Vec::new()), &mut out);
+        assert_eq!("O-O-O+", out);
+
+        out.clear();
+        write_san_move(&Node::NullMove(Check::None, Vec::new()), &mut out);
+        assert_eq!("--", out);
+    }
+    #[test]
     fn test_san_pawn_capture() {
-        assert_eq!(Done(&b""[..], Node::Move(PAWN, Source::File(FILE_B), MoveOrCapture::Capture, SQ_C1, Promotion::PieceType(ROOK), Check::Check, MoveAnnotation::None)), san_pawn_capture(&b"bxc1=R+"[..]));
+        assert_eq!(Done(&b""[..], Node::Move(PAWN, Source::File(FILE_B), MoveOrCapture::Capture, SQ_C1, Promotion::PieceType(ROOK), Check::Check, Vec::new())), san_pawn_capture(&b"bxc1=R+"[..]));
     }
     #[test]
     fn test_san_move_parsing() {
-        assert_eq!(Done(&b""[..], Node::Move(PAWN, Source::None, MoveOrCapture::Move, SQ_E4, Promotion::None, Check::None, MoveAnnotation::None)), san_move(&b"e4"[..]));
-        assert_eq!(Done(&b""[..], Node::Move(PAWN, Source::None, MoveOrCapture::Move, SQ_D4, Promotion::None, Check::None, MoveAnnotation::None)), san_move(&b"d4"[..]));
-        assert_eq!(Done(&b""[..], Node::Move(PAWN, Source::None, MoveOrCapture::Move, SQ_C4, Promotion::None, Check::None, MoveAnnotation::None)), san_move(&b"c4"[..]));
+        assert_eq!(Done(&b""[..], Node::Move(PAWN, Source::None, MoveOrCapture::Move, SQ_E4, Promotion::None, Check::None, Vec::new())), san_move(&b"e4"[..]));
+        assert_eq!(Done(&b""[..], Node::Move(PAWN, Source::None, MoveOrCapture::Move, SQ_D4, Promotion::None, Check::None, Vec::new())), san_move(&b"d4"[..]));
+        assert_eq!(Done(&b""[..], Node::Move(PAWN, Source::None, MoveOrCapture::Move, SQ_C4, Promotion::None, Check::None, Vec::new())), san_move(&b"c4"[..]));
 
-        assert_eq!(Done(&b""[..], Node::Move(PAWN, Source::File(FILE_D), MoveOrCapture::Capture, SQ_E4, Promotion::None, Check::None, MoveAnnotation::None)), san_move(&b"dxe4"[..]));
-        assert_eq!(Done(&b""[..], Node::Move(PAWN, Source::File(FILE_E), MoveOrCapture::Capture, SQ_D4, Promotion::None, Check::None, MoveAnnotation::None)), san_move(&b"exd4"[..]));
-        assert_eq!(Done(&b""[..], Node::Move(PAWN, Source::File(FILE_D), MoveOrCapture::Capture, SQ_C4, Promotion::None, Check::None, MoveAnnotation::None)), san_move(&b"dxc4"[..]));
+        assert_eq!(Done(&b""[..], Node::Move(PAWN, Source::File(FILE_D), MoveOrCapture::Capture, SQ_E4, Promotion::None, Check::None, Vec::new())), san_move(&b"dxe4"[..]));
+        assert_eq!(Done(&b""[..], Node::Move(PAWN, Source::File(FILE_E), MoveOrCapture::Capture, SQ_D4, Promotion::None, Check::None, Vec::new())), san_move(&b"exd4"[..]));
+        assert_eq!(Done(&b""[..], Node::Move(PAWN, Source::File(FILE_D), MoveOrCapture::Capture, SQ_C4, Promotion::None, Check::None, Vec::new())), san_move(&b"dxc4"[..]));
 
-        assert_eq!(Done(&b""[..], Node::Move(KNIGHT, Source::None, MoveOrCapture::Move, SQ_F3, Promotion::None, Check::None, MoveAnnotation::None)), san_move(&b"Nf3"[..]));
-        assert_eq!(Done(&b""[..], Node::Move(BISHOP, Source::None, MoveOrCapture::Move, SQ_B5, Promotion::None, Check::None, MoveAnnotation::None)), san_move(&b"Bb5"[..]));
-        assert_eq!(Done(&b""[..], Node::Move(QUEEN, Source::None, MoveOrCapture::Move, SQ_D8, Promotion::None, Check::None, MoveAnnotation::None)), san_move(&b"Qd8"[..]));
-        assert_eq!(Done(&b""[..], Node::Move(ROOK, Source::None, MoveOrCapture::Move, SQ_D1, Promotion::None, Check::None, MoveAnnotation::None)), san_move(&b"Rd1"[..]));
+        assert_eq!(Done(&b""[..], Node::Move(KNIGHT, Source::None, MoveOrCapture::Move, SQ_F3, Promotion::None, Check::None, Vec::new())), san_move(&b"Nf3"[..]));
+        assert_eq!(Done(&b""[..], Node::Move(BISHOP, Source::None, MoveOrCapture::Move, SQ_B5, Promotion::None, Check::None, Vec::new())), san_move(&b"Bb5"[..]));
+        assert_eq!(Done(&b""[..], Node::Move(QUEEN, Source::None, MoveOrCapture::Move, SQ_D8, Promotion::None, Check::None, Vec::new())), san_move(&b"Qd8"[..]));
+        assert_eq!(Done(&b""[..], Node::Move(ROOK, Source::None, MoveOrCapture::Move, SQ_D1, Promotion::None, Check::None, Vec::new())), san_move(&b"Rd1"[..]));
 
-        assert_eq!(Done(&b""[..], Node::Move(KNIGHT, Source::None, MoveOrCapture::Capture, SQ_F3, Promotion::None, Check::None, MoveAnnotation::None)), san_move(&b"Nxf3"[..]));
-        assert_eq!(Done(&b""[..], Node::Move(BISHOP, Source::None, MoveOrCapture::Capture, SQ_B5, Promotion::None, Check::None, MoveAnnotation::None)), san_move(&b"Bxb5"[..]));
-        assert_eq!(Done(&b""[..], Node::Move(QUEEN, Source::None, MoveOrCapture::Capture, SQ_D8, Promotion::None, Check::None, MoveAnnotation::None)), san_move(&b"Qxd8"[..]));
-        assert_eq!(Done(&b""[..], Node::Move(ROOK, Source::None, MoveOrCapture::Capture, SQ_D1, Promotion::None, Check::None, MoveAnnotation::None)), san_move(&b"Rxd1"[..]));
+        assert_eq!(Done(&b""[..], Node::Move(KNIGHT, Source::None, MoveOrCapture::Capture, SQ_F3, Promotion::None, Check::None, Vec::new())), san_move(&b"Nxf3"[..]));
+        assert_eq!(Done(&b""[..], Node::Move(BISHOP, Source::None, MoveOrCapture::Capture, SQ_B5, Promotion::None, Check::None, Vec::new())), san_move(&b"Bxb5"[..]));
+        assert_eq!(Done(&b""[..], Node::Move(QUEEN, Source::None, MoveOrCapture::Capture, SQ_D8, Promotion::None, Check::None, Vec::new())), san_move(&b"Qxd8"[..]));
+        assert_eq!(Done(&b""[..], Node::Move(ROOK, Source::None, MoveOrCapture::Capture, SQ_D1, Promotion::None, Check::None, Vec::new())), san_move(&b"Rxd1"[..]));
 
-        assert_eq!(Done(&b""[..], Node::Move(KNIGHT, Source::File(FILE_E), MoveOrCapture::Move, SQ_F3, Promotion::None, Check::None, MoveAnnotation::None)), san_move(&b"Nef3"[..]));
-        assert_eq!(Done(&b""[..], Node::Move(ROOK, Source::File(FILE_E), MoveOrCapture::Move, SQ_D1, Promotion::None, Check::None, MoveAnnotation::None)), san_move(&b"Red1"[..]));
+        assert_eq!(Done(&b""[..], Node::Move(KNIGHT, Source::File(FILE_E), MoveOrCapture::Move, SQ_F3, Promotion::None, Check::None, Vec::new())), san_move(&b"Nef3"[..]));
+        assert_eq!(Done(&b""[..], Node::Move(ROOK, Source::File(FILE_E), MoveOrCapture::Move, SQ_D1, Promotion::None, Check::None, Vec::new())), san_move(&b"Red1"[..]));
 
-        assert_eq!(Done(&b""[..], Node::Move(KNIGHT, Source::File(FILE_E), MoveOrCapture::Capture, SQ_F3, Promotion::None, Check::None, MoveAnnotation::None)), san_move(&b"Nexf3"[..]));
-        assert_eq!(Done(&b""[..], Node::Move(ROOK, Source::File(FILE_E), MoveOrCapture::Capture, SQ_D1, Promotion::None, Check::None, MoveAnnotation::None)), san_move(&b"Rexd1"[..]));
+        assert_eq!(Done(&b""[..], Node::Move(KNIGHT, Source::File(FILE_E), MoveOrCapture::Capture, SQ_F3, Promotion::None, Check::None, Vec::new())), san_move(&b"Nexf3"[..]));
+        assert_eq!(Done(&b""[..], Node::Move(ROOK, Source::File(FILE_E), MoveOrCapture::Capture, SQ_D1, Promotion::None, Check::None, Vec::new())), san_move(&b"Rexd1"[..]));
 
-        assert_eq!(Done(&b""[..], Node::Move(KNIGHT, Source::Rank(RANK_2), MoveOrCapture::Move, SQ_F3, Promotion::None, Check::None, MoveAnnotation::None)), san_move(&b"N2f3"[..]));
-        assert_eq!(Done(&b""[..], Node::Move(ROOK, Source::Rank(RANK_3), MoveOrCapture::Move, SQ_D1, Promotion::None, Check::None, MoveAnnotation::None)), san_move(&b"R3d1"[..]));
+        assert_eq!(Done(&b""[..], Node::Move(KNIGHT, Source::Rank(RANK_2), MoveOrCapture::Move, SQ_F3, Promotion::None, Check::None, Vec::new())), san_move(&b"N2f3"[..]));
+        assert_eq!(Done(&b""[..], Node::Move(ROOK, Source::Rank(RANK_3), MoveOrCapture::Move, SQ_D1, Promotion::None, Check::None, Vec::new())), san_move(&b"R3d1"[..]));
 
-        assert_eq!(Done(&b""[..], Node::Move(KNIGHT, Source::Rank(RANK_1), MoveOrCapture::Capture, SQ_F3, Promotion::None, Check::None, MoveAnnotation::None)), san_move(&b"N1xf3"[..]));
-        assert_eq!(Done(&b""[..], Node::Move(ROOK, Source::Rank(RANK_6), MoveOrCapture::Capture, SQ_D1, Promotion::None, Check::None, MoveAnnotation::None)), san_move(&b"R6xd1"[..]));
+        assert_eq!(Done(&b""[..], Node::Move(KNIGHT, Source::Rank(RANK_1), MoveOrCapture::Capture, SQ_F3, Promotion::None, Check::None, Vec::new())), san_move(&b"N1xf3"[..]));
+        assert_eq!(Done(&b""[..], Node::Move(ROOK, Source::Rank(RANK_6), MoveOrCapture::Capture, SQ_D1, Promotion::None, Check::None, Vec::new())), san_move(&b"R6xd1"[..]));
         
-        assert_eq!(Done(&b""[..], Node::Move(KNIGHT, Source::Square(SQ_F1), MoveOrCapture::Move, SQ_F3, Promotion::None, Check::None, MoveAnnotation::None)), san_move(&b"Nf1f3"[..]));
-        assert_eq!(Done(&b""[..], Node::Move(ROOK, Source::Square(SQ_D3), MoveOrCapture::Move, SQ_D1, Promotion::None, Check::None, MoveAnnotation::None)), san_move(&b"Rd3d1"[..]));
+        assert_eq!(Done(&b""[..], Node::Move(KNIGHT, Source::Square(SQ_F1), MoveOrCapture::Move, SQ_F3, Promotion::None, Check::None, Vec::new())), san_move(&b"Nf1f3"[..]));
+        assert_eq!(Done(&b""[..], Node::Move(ROOK, Source::Square(SQ_D3), MoveOrCapture::Move, SQ_D1, Promotion::None, Check::None, Vec::new())), san_move(&b"Rd3d1"[..]));
+
+        assert_eq!(Done(&b""[..], Node::Move(KNIGHT, Source::Square(SQ_F1), MoveOrCapture::Capture, SQ_F3, Promotion::None, Check::None, Vec::new())), san_move(&b"Nf1xf3"[..]));
+        assert_eq!(Done(&b""[..], Node::Move(ROOK, Source::Square(SQ_D3), MoveOrCapture::Capture, SQ_D1, Promotion::None, Check::None, Vec::new())), san_move(&b"Rd3xd1"[..]));
+
+        assert_eq!(Done(&b""[..], Node::Move(PAWN, Source::None, MoveOrCapture::Move, SQ_E8, Promotion::PieceType(QUEEN), Check::None, Vec::new())), san_move(&b"e8=Q"[..]));
+        assert_eq!(Done(&b""[..], Node::Move(PAWN, Source::File(FILE_F), MoveOrCapture::Capture, SQ_E8, Promotion::PieceType(KNIGHT), Check::None, Vec::new())), san_move(&b"fxe8=N"[..]));
 
-        assert_eq!(Done(&b""[..], Node::Move(KNIGHT, Source::Square(SQ_F1), MoveOrCapture::Capture, SQ_F3, Promotion::None, Check::None, MoveAnnotation::None)), san_move(&b"Nf1xf3"[..]));
-        assert_eq!(Done(&b""[..], Node::Move(ROOK, Source::Square(SQ_D3), MoveOrCapture::Capture, SQ_D1, Promotion::None, Check::None, MoveAnnotation::None)), san_move(&b"Rd3xd1"[..]));
+        assert_eq!(Done(&b""[..], Node::Move(PAWN, Source::None, MoveOrCapture::Move, SQ_E8, Promotion::PieceType(QUEEN), Check::Check, Vec::new())), san_move(&b"e8=Q+"[..]));
+        assert_eq!(Done(&b""[..], Node::Move(PAWN, Source::File(FILE_F), MoveOrCapture::Capture, SQ_E8, Promotion::PieceType(KNIGHT), Check::Checkmate, Vec::new())), san_move(&b"fxe8=N#"[..]));
 
-        assert_eq!(Done(&b""[..], Node::Move(PAWN, Source::None, MoveOrCapture::Move, SQ_E8, Promotion::PieceType(QUEEN), Check::None, MoveAnnotation::None)), san_move(&b"e8=Q"[..]));
-        assert_eq!(Done(&b""[..], Node::Move(PAWN, Source::File(FILE_F), MoveOrCapture::Capture, SQ_E8, Promotion::PieceType(KNIGHT), Check::None, MoveAnnotation::None)), san_move(&b"fxe8=N"[..]));
+        assert_eq!(Done(&b""[..], Node::Move(QUEEN, Source::Square(SQ_A6), MoveOrCapture::Capture, SQ_B7, Promotion::None, Check::Checkmate, Vec::new())), san_move(&b"Qa6xb7#"[..]));
 
-        assert_eq!(Done(&b""[..], Node::Move(PAWN, Source::None, MoveOrCapture::Move, SQ_E8, Promotion::PieceType(QUEEN), Check::Check, MoveAnnotation::None)), san_move(&b"e8=Q+"[..]));
-        assert_eq!(Done(&b""[..], Node::Move(PAWN, Source::File(FILE_F), MoveOrCapture::Capture, SQ_E8, Promotion::PieceType(KNIGHT), Check::Checkmate, MoveAnnotation::None)), san_move(&b"fxe8=N#"[..]));
+        assert_eq!(Done(&b""[..], Node::Move(QUEEN, Source::Square(SQ_A6), MoveOrCapture::Capture, SQ_B7, Promotion::None, Check::Checkmate, vec![3])), san_move(&b"Qa6xb7#!!"[..]));
 
-        assert_eq!(Done(&b""[..], Node::Move(QUEEN, Source::Square(SQ_A6), MoveOrCapture::Capture, SQ_B7, Promotion::None, Check::Checkmate, MoveAnnotation::None)), san_move(&b"Qa6xb7#"[..]));
+        assert_eq!(Done(&b""[..], Node::CastleKingSide(Check::None, Vec::new())), san_move(&b"O-O"[..]));
+        assert_eq!(Done(&b""[..], Node::CastleQueenSide(Check::None, Vec::new())), san_move(&b"O-O-O"[..]));
 
-        assert_eq!(Done(&b""[..], Node::Move(QUEEN, Source::Square(SQ_A6), MoveOrCapture::Capture, SQ_B7, Promotion::None, Check::Checkmate, MoveAnnotation::Brilliant)), san_move(&b"Qa6xb7#!!"[..]));
+        assert_eq!(Done(&b""[..], Node::CastleKingSide(Check::Checkmate, vec![3])), san_move(&b"O-O#!!"[..]));
+        assert_eq!(Done(&b""[..], Node::CastleQueenSide(Check::Checkmate, vec![3])), san_move(&b"O-O-O#!!"[..]));
 
-        assert_eq!(Done(&b""[..], Node::CastleKingSide(Check::None, MoveAnnotation::None)), san_move(&b"O-O"[..]));
-        assert_eq!(Done(&b""[..], Node::CastleQueenSide(Check::None, MoveAnnotation::None)), san_move(&b"O-O-O"[..]));
+        assert_eq!(Done(&b""[..], Node::NullMove(Check::Checkmate, vec![3])), san_move(&b"--#!!"[..]));
+        assert_eq!(Done(&b""[..], Node::NullMove(Check::Checkmate, vec![3])), san_move(&b"Z0#!!"[..]));
+        assert_eq!(Done(&b""[..], Node::NullMove(Check::Checkmate, vec![3])), san_move(&b"z0#!!"[..]));
+    }
+
+    #[test]
+    fn test_san_nag() {
+        assert_eq!(Done(&b""[..], 1u8), san_nag(&b"$1"[..]));
+        assert_eq!(Done(&b""[..], 16u8), san_nag(&b"$16"[..]));
+        assert_eq!(Done(&b""[..], 255u8), san_nag(&b"$255"[..]));
+    }
+
+    #[test]
+    fn test_san_move_annotation_unifies_with_its_canonical_nag() {
+        // The suffix spelling and the numeric spelling of the same
+        // annotation must parse to the same glyph code.
+        assert_eq!(san_move_annotation(&b"!"[..]), san_nag(&b"$1"[..]));
+        assert_eq!(san_move_annotation(&b"?"[..]), san_nag(&b"$2"[..]));
+        assert_eq!(san_move_annotation(&b"!!"[..]), san_nag(&b"$3"[..]));
+        assert_eq!(san_move_annotation(&b"??"[..]), san_nag(&b"$4"[..]));
+        assert_eq!(san_move_annotation(&b"!?"[..]), san_nag(&b"$5"[..]));
+        assert_eq!(san_move_annotation(&b"?!"[..]), san_nag(&b"$6"[..]));
+    }
 
-        assert_eq!(Done(&b""[..], Node::CastleKingSide(Check::Checkmate, MoveAnnotation::Brilliant)), san_move(&b"O-O#!!"[..]));
-        assert_eq!(Done(&b""[..], Node::CastleQueenSide(Check::Checkmate, MoveAnnotation::Brilliant)), san_move(&b"O-O-O#!!"[..]));
+    #[test]
+    fn test_san_move_carries_several_nags() {
+        assert_eq!(
+            Done(&b""[..], Node::Move(PAWN, Source::None, MoveOrCapture::Move, SQ_E4, Promotion::None, Check::None, vec![16, 14])),
+            san_move(&b"e4$16$14"[..])
+        );
+    }
+
+    #[test]
+    fn test_uci_move() {
+        assert_eq!(
+            Done(&b""[..], Node::Move(NO_PIECE_TYPE, Source::Square(SQ_E2), MoveOrCapture::Move, SQ_E4, Promotion::None, Check::None, Vec::new())),
+            uci_move(&b"e2e4"[..])
+        );
+        assert_eq!(
+            Done(&b""[..], Node::Move(NO_PIECE_TYPE, Source::Square(SQ_E7), MoveOrCapture::Move, SQ_E8, Promotion::PieceType(QUEEN), Check::None, Vec::new())),
+            uci_move(&b"e7e8q"[..])
+        );
+        assert_eq!(Done(&b""[..], Node::NullMove(Check::None, Vec::new())), uci_move(&b"0000"[..]));
+    }
+
+    #[test]
+    fn test_parse_san_or_uci() {
+        // SAN still wins when it applies.
+        assert_eq!(
+            Ok(Node::Move(PAWN, Source::None, MoveOrCapture::Move, SQ_E4, Promotion::None, Check::None, Vec::new())),
+            parse_san_or_uci(b"e4")
+        );
+        // Falls back to long algebraic when SAN can't make sense of it.
+        assert_eq!(
+            Ok(Node::Move(NO_PIECE_TYPE, Source::Square(SQ_E2), MoveOrCapture::Move, SQ_E4, Promotion::None, Check::None, Vec::new())),
+            parse_san_or_uci(b"e2e4")
+        );
+        assert_eq!(Ok(Node::NullMove(Check::None, Vec::new())), parse_san_or_uci(b"0000"));
+        assert_eq!(Err(SanParseError::NotAMove), parse_san_or_uci(b"zzzz"));
+    }
+
+    #[test]
+    fn test_parse_san() {
+        assert_eq!(
+            Ok(Node::Move(PAWN, Source::None, MoveOrCapture::Move, SQ_E4, Promotion::None, Check::None, Vec::new())),
+            parse_san(b"e4")
+        );
+        assert_eq!(
+            Ok(Node::Move(KNIGHT, Source::None, MoveOrCapture::Capture, SQ_F3, Promotion::None, Check::Checkmate, vec![3])),
+            parse_san(b"Nxf3#!!")
+        );
+        assert_eq!(Err(SanParseError::EmptyInput), parse_san(b""));
+        assert_eq!(Err(SanParseError::UnexpectedTrailing(b"x".to_vec())), parse_san(b"e4x"));
+        assert_eq!(Err(SanParseError::DanglingPromotionMarker), parse_san(b"e8="));
+        assert_eq!(Err(SanParseError::NotAMove), parse_san(b"N"));
+    }
+
+    #[test]
+    fn test_node_from_str() {
+        assert_eq!(
+            Ok(Node::Move(PAWN, Source::None, MoveOrCapture::Move, SQ_E4, Promotion::None, Check::None, Vec::new())),
+            "e4".parse::<Node>()
+        );
+        assert_eq!(Err(SanParseError::EmptyInput), "".parse::<Node>());
+    }
 
-        assert_eq!(Done(&b""[..], Node::NullMove(Check::Checkmate, MoveAnnotation::Brilliant)), san_move(&b"--#!!"[..]));
-        assert_eq!(Done(&b""[..], Node::NullMove(Check::Checkmate, MoveAnnotation::Brilliant)), san_move(&b"Z0#!!"[..]));
-        assert_eq!(Done(&b""[..], Node::NullMove(Check::Checkmate, MoveAnnotation::Brilliant)), san_move(&b"z0#!!"[..]));
+    #[test]
+    fn test_resolve_san_unambiguous_pawn_push() {
+        let pos = Position::set("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", false).unwrap();
+        let node = Node::Move(PAWN, Source::None, MoveOrCapture::Move, SQ_E4, Promotion::None, Check::None, Vec::new());
+        assert_eq!(
+            Ok(ResolvedMove { mv: make_move_simple(SQ_E2, SQ_E4), piece: PAWN }),
+            resolve_san(node, &pos)
+        );
+    }
+
+    #[test]
+    fn test_resolve_san_ambiguous_knight_move_needs_disambiguation() {
+        let pos = Position::set("4k3/8/8/8/8/8/8/N3NK2 w - - 0 1", false).unwrap();
+        let node = Node::Move(KNIGHT, Source::None, MoveOrCapture::Move, SQ_C2, Promotion::None, Check::None, Vec::new());
+        assert_eq!(Err(SanError::Ambiguous), resolve_san(node, &pos));
+
+        let node = Node::Move(KNIGHT, Source::File(FILE_A), MoveOrCapture::Move, SQ_C2, Promotion::None, Check::None, Vec::new());
+        assert_eq!(
+            Ok(ResolvedMove { mv: make_move_simple(SQ_A1, SQ_C2), piece: KNIGHT }),
+            resolve_san(node, &pos)
+        );
+    }
+
+    #[test]
+    fn test_resolve_san_rejects_illegal_target() {
+        let pos = Position::set("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", false).unwrap();
+        let node = Node::Move(KNIGHT, Source::None, MoveOrCapture::Move, SQ_E5, Promotion::None, Check::None, Vec::new());
+        assert_eq!(Err(SanError::Illegal), resolve_san(node, &pos));
+    }
+
+    #[test]
+    fn test_resolve_san_drops_candidate_that_leaves_king_in_check() {
+        // The white knight on d2 is pinned to its king by the rook on
+        // d8 -- capturing the black knight on e4 would walk off the
+        // d-file and expose the king, so the otherwise-reachable target
+        // must be dropped as illegal rather than resolved.
+        let pos = Position::set("3r3k/8/8/8/4n3/8/3N4/3K4 w - - 0 1", false).unwrap();
+        let node = Node::Move(KNIGHT, Source::None, MoveOrCapture::Capture, SQ_E4, Promotion::None, Check::None, Vec::new());
+        assert_eq!(Err(SanError::Illegal), resolve_san(node, &pos));
+    }
+
+    #[test]
+    fn test_resolve_san_castle_king_side() {
+        let pos = Position::set("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", false).unwrap();
+        assert_eq!(
+            Ok(ResolvedMove { mv: make_move_castling(SQ_E1, SQ_H1), piece: KING }),
+            resolve_san(Node::CastleKingSide(Check::None, Vec::new()), &pos)
+        );
+    }
+
+    #[test]
+    fn test_resolve_san_castle_through_check_is_illegal() {
+        // The path squares (f1/g1) are clear, but the black rook on g8
+        // rakes straight down the g-file onto the king's landing square.
+        let pos = Position::set("r3k1r1/8/8/8/8/8/8/R3K2R w KQkq - 0 1", false).unwrap();
+        assert_eq!(
+            Err(SanError::Illegal),
+            resolve_san(Node::CastleKingSide(Check::None, Vec::new()), &pos)
+        );
+    }
+
+    #[test]
+    fn test_resolve_san_en_passant() {
+        let pos = Position::set("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", false).unwrap();
+        let node = Node::Move(PAWN, Source::File(FILE_E), MoveOrCapture::Capture, SQ_D6, Promotion::None, Check::None, Vec::new());
+        assert_eq!(
+            Ok(ResolvedMove { mv: make_move_enpassant(SQ_E5, SQ_D6), piece: PAWN }),
+            resolve_san(node, &pos)
+        );
+    }
+
+    #[test]
+    fn test_resolve_san_promotion_required_on_last_rank() {
+        let pos = Position::set("8/4P3/8/8/8/8/8/4K2k w - - 0 1", false).unwrap();
+        let node = Node::Move(PAWN, Source::None, MoveOrCapture::Move, SQ_E8, Promotion::None, Check::None, Vec::new());
+        assert_eq!(Err(SanError::BadPromotion), resolve_san(node, &pos));
+
+        let node = Node::Move(PAWN, Source::None, MoveOrCapture::Move, SQ_E8, Promotion::PieceType(QUEEN), Check::None, Vec::new());
+        assert_eq!(
+            Ok(ResolvedMove { mv: make_move_promotion(SQ_E7, SQ_E8, QUEEN), piece: PAWN }),
+            resolve_san(node, &pos)
+        );
+    }
+
+    #[test]
+    fn test_resolve_san_invalid_move_and_null_move() {
+        let pos = Position::set("4k3/8/8/8/8/8/8/4K3 w - - 0 1", false).unwrap();
+        assert_eq!(Err(SanError::InvalidMove), resolve_san(Node::InvalidMove, &pos));
+        assert_eq!(
+            Ok(ResolvedMove { mv: MOVE_NULL, piece: NO_PIECE_TYPE }),
+            resolve_san(Node::NullMove(Check::None, Vec::new()), &pos)
+        );
+    }
+
+    #[test]
+    fn test_encode_san_unambiguous_pawn_push() {
+        let pos = Position::set("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", false).unwrap();
+        assert_eq!("e4", encode_san(make_move_simple(SQ_E2, SQ_E4), &pos));
+    }
+
+    #[test]
+    fn test_encode_san_capture() {
+        let pos = Position::set("4k3/8/8/8/4n3/8/3N4/4K3 w - - 0 1", false).unwrap();
+        assert_eq!("Nxe4", encode_san(make_move_simple(SQ_D2, SQ_E4), &pos));
+    }
+
+    #[test]
+    fn test_encode_san_disambiguates_by_file() {
+        let pos = Position::set("4k3/8/8/8/8/8/8/N3NK2 w - - 0 1", false).unwrap();
+        assert_eq!("Nac2", encode_san(make_move_simple(SQ_A1, SQ_C2), &pos));
+    }
+
+    #[test]
+    fn test_encode_san_disambiguates_by_rank() {
+        // Knights on d2 and d6 both reach e4: same file, so the rank has
+        // to carry the disambiguation instead.
+        let pos = Position::set("k7/8/3N4/8/8/8/3N4/K7 w - - 0 1", false).unwrap();
+        assert_eq!("N2e4", encode_san(make_move_simple(SQ_D2, SQ_E4), &pos));
+    }
+
+    #[test]
+    fn test_encode_san_disambiguates_by_full_square() {
+        // d2 shares a file with d6 and a rank with f2 -- neither alone
+        // narrows it down, so the full origin square is required.
+        let pos = Position::set("k7/8/3N4/8/8/8/3N1N2/K7 w - - 0 1", false).unwrap();
+        assert_eq!("Nd2e4", encode_san(make_move_simple(SQ_D2, SQ_E4), &pos));
+    }
+
+    #[test]
+    fn test_encode_san_promotion() {
+        let pos = Position::set("8/4P3/8/8/8/8/8/4K2k w - - 0 1", false).unwrap();
+        assert_eq!("e8=Q", encode_san(make_move_promotion(SQ_E7, SQ_E8, QUEEN), &pos));
+    }
+
+    #[test]
+    fn test_encode_san_castle_king_side() {
+        let pos = Position::set("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", false).unwrap();
+        assert_eq!("O-O", encode_san(make_move_castling(SQ_E1, SQ_H1), &pos));
+    }
+
+    #[test]
+    fn test_encode_san_check_suffix() {
+        let pos = Position::set("4k3/8/8/8/7R/8/8/4K3 w - - 0 1", false).unwrap();
+        assert_eq!("Re4+", encode_san(make_move_simple(SQ_H4, SQ_E4), &pos));
+    }
+
+    #[test]
+    fn test_encode_san_checkmate_suffix() {
+        // The g8 king is boxed in by its own pawns; Re8 mates along the
+        // back rank.
+        let pos = Position::set("6k1/5ppp/8/8/8/8/8/K3R3 w - - 0 1", false).unwrap();
+        assert_eq!("Re8#", encode_san(make_move_simple(SQ_E1, SQ_E8), &pos));
+    }
+
+    #[test]
+    fn test_encode_san_round_trips_through_parse_and_resolve() {
+        let pos = Position::set("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", false).unwrap();
+        let node = match san_move(&b"e4"[..]) {
+            Done(_, node) => node,
+            _ => panic!("failed to parse e4"),
+        };
+        let resolved = resolve_san(node, &pos).unwrap();
+        assert_eq!("e4", encode_san(resolved.mv, &pos));
     }
 }
@@ -0,0 +1,377 @@
+// This file is part of the samson library.
+//
+// Copyright (C) 2017 Lakin Wecker <lakin@wecker.ca>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+///-------------------------------------------------------------------------------------------------
+/// Parsers for the textual UCI engine protocol: both the GUI->engine commands
+/// and the engine->GUI responses. The move-related arms reuse the existing
+/// `uci`/`square` combinators from the parent module instead of re-deriving
+/// move syntax.
+///-------------------------------------------------------------------------------------------------
+
+use super::super::types::*;
+use super::fen;
+
+use std::str;
+use std::str::FromStr;
+
+///-------------------------------------------------------------------------------------------------
+#[derive(Clone, Debug, PartialEq)]
+pub enum PositionRoot {
+    StartPos,
+    Fen(Vec<fen::Node>)
+}
+
+///-------------------------------------------------------------------------------------------------
+#[derive(Clone, Debug, PartialEq)]
+pub enum GoParam {
+    WTime(u32),
+    BTime(u32),
+    WInc(u32),
+    BInc(u32),
+    MovesToGo(u16),
+    Depth(u16),
+    Nodes(u64),
+    MoveTime(u32),
+    Infinite,
+    Ponder,
+    SearchMoves(Vec<Move>)
+}
+
+///-------------------------------------------------------------------------------------------------
+#[derive(Clone, Debug, PartialEq)]
+pub enum UciCommand<'a> {
+    Uci,
+    IsReady,
+    UciNewGame,
+    Position(PositionRoot, Vec<Move>),
+    Go(Vec<GoParam>),
+    Stop,
+    PonderHit,
+    SetOption{ name: &'a [u8], value: Option<&'a [u8]> }
+}
+
+///-------------------------------------------------------------------------------------------------
+#[derive(Clone, Debug, PartialEq)]
+pub enum Score {
+    Centipawns(i32),
+    Mate(i32)
+}
+
+///-------------------------------------------------------------------------------------------------
+#[derive(Clone, Debug, PartialEq)]
+pub enum InfoParam {
+    Depth(u16),
+    SelDepth(u16),
+    Score(Score),
+    Nodes(u64),
+    Nps(u64),
+    Time(u64),
+    MultiPv(u16),
+    CurrMove(Move),
+    Pv(Vec<Move>)
+}
+
+///-------------------------------------------------------------------------------------------------
+#[derive(Clone, Debug, PartialEq)]
+pub enum UciResponse<'a> {
+    IdName(&'a [u8]),
+    IdAuthor(&'a [u8]),
+    UciOk,
+    ReadyOk,
+    BestMove{ mv: Move, ponder: Option<Move> },
+    Info(Vec<InfoParam>),
+    Option{ name: &'a [u8], rest: &'a [u8] }
+}
+
+///-------------------------------------------------------------------------------------------------
+named!(uint_token<u64>, map_res!(map_res!(is_a!("0123456789"), str::from_utf8), FromStr::from_str));
+
+///-------------------------------------------------------------------------------------------------
+named!(int_token<i32>,
+    map_res!(map_res!(recognize!(pair!(opt!(char!('-')), is_a!("0123456789"))), str::from_utf8), FromStr::from_str)
+);
+
+///-------------------------------------------------------------------------------------------------
+named!(pub moves_list<Vec<Move> >, separated_list!(char!(' '), super::uci));
+
+///-------------------------------------------------------------------------------------------------
+named!(pub position_root<PositionRoot>,
+    alt_complete!(
+        map!(tag!("startpos"), |_| PositionRoot::StartPos) |
+        map!(preceded!(tag!("fen "), fen::fen), |nodes| PositionRoot::Fen(nodes))
+    )
+);
+
+///-------------------------------------------------------------------------------------------------
+named!(pub position_command<UciCommand>,
+    do_parse!(
+        tag!("position ") >>
+        root: position_root >>
+        moves: opt!(complete!(preceded!(tag!(" moves "), moves_list))) >>
+        (UciCommand::Position(root, moves.unwrap_or_else(Vec::new)))
+    )
+);
+
+///-------------------------------------------------------------------------------------------------
+named!(pub go_param<GoParam>,
+    alt_complete!(
+        map!(preceded!(tag!("wtime "), uint_token), |n| GoParam::WTime(n as u32)) |
+        map!(preceded!(tag!("btime "), uint_token), |n| GoParam::BTime(n as u32)) |
+        map!(preceded!(tag!("winc "), uint_token), |n| GoParam::WInc(n as u32)) |
+        map!(preceded!(tag!("binc "), uint_token), |n| GoParam::BInc(n as u32)) |
+        map!(preceded!(tag!("movestogo "), uint_token), |n| GoParam::MovesToGo(n as u16)) |
+        map!(preceded!(tag!("depth "), uint_token), |n| GoParam::Depth(n as u16)) |
+        map!(preceded!(tag!("nodes "), uint_token), |n| GoParam::Nodes(n)) |
+        map!(preceded!(tag!("movetime "), uint_token), |n| GoParam::MoveTime(n as u32)) |
+        map!(preceded!(tag!("searchmoves "), moves_list), |m| GoParam::SearchMoves(m)) |
+        map!(tag!("infinite"), |_| GoParam::Infinite) |
+        map!(tag!("ponder"), |_| GoParam::Ponder)
+    )
+);
+
+///-------------------------------------------------------------------------------------------------
+named!(pub go_command<UciCommand>,
+    do_parse!(
+        tag!("go") >>
+        params: many0!(preceded!(char!(' '), go_param)) >>
+        (UciCommand::Go(params))
+    )
+);
+
+///-------------------------------------------------------------------------------------------------
+named!(pub setoption_command<UciCommand>,
+    do_parse!(
+        tag!("setoption name ") >>
+        parsed: alt_complete!(
+            do_parse!(
+                name: take_until!(" value ") >>
+                tag!(" value ") >>
+                value: is_not!("\n") >>
+                (name, Some(value))
+            ) |
+            map!(is_not!("\n"), |n| (n, None))
+        ) >>
+        ({
+            let (name, value) = parsed;
+            UciCommand::SetOption{ name: name, value: value }
+        })
+    )
+);
+
+///-------------------------------------------------------------------------------------------------
+named!(pub uci_command<UciCommand>,
+    alt_complete!(
+        map!(tag!("ucinewgame"), |_| UciCommand::UciNewGame) |
+        map!(tag!("uci"), |_| UciCommand::Uci) |
+        map!(tag!("isready"), |_| UciCommand::IsReady) |
+        position_command |
+        go_command |
+        map!(tag!("stop"), |_| UciCommand::Stop) |
+        map!(tag!("ponderhit"), |_| UciCommand::PonderHit) |
+        setoption_command
+    )
+);
+
+///-------------------------------------------------------------------------------------------------
+named!(pub score_value<Score>,
+    alt_complete!(
+        map!(preceded!(tag!("cp "), int_token), |n| Score::Centipawns(n)) |
+        map!(preceded!(tag!("mate "), int_token), |n| Score::Mate(n))
+    )
+);
+
+///-------------------------------------------------------------------------------------------------
+named!(pub info_param<InfoParam>,
+    alt_complete!(
+        map!(preceded!(tag!("depth "), uint_token), |n| InfoParam::Depth(n as u16)) |
+        map!(preceded!(tag!("seldepth "), uint_token), |n| InfoParam::SelDepth(n as u16)) |
+        map!(preceded!(tag!("score "), score_value), |s| InfoParam::Score(s)) |
+        map!(preceded!(tag!("nodes "), uint_token), |n| InfoParam::Nodes(n)) |
+        map!(preceded!(tag!("nps "), uint_token), |n| InfoParam::Nps(n)) |
+        map!(preceded!(tag!("time "), uint_token), |n| InfoParam::Time(n)) |
+        map!(preceded!(tag!("multipv "), uint_token), |n| InfoParam::MultiPv(n as u16)) |
+        map!(preceded!(tag!("currmove "), super::uci), |m| InfoParam::CurrMove(m)) |
+        map!(preceded!(tag!("pv "), moves_list), |m| InfoParam::Pv(m))
+    )
+);
+
+///-------------------------------------------------------------------------------------------------
+named!(pub info_response<UciResponse>,
+    do_parse!(
+        tag!("info") >>
+        params: many0!(preceded!(char!(' '), info_param)) >>
+        (UciResponse::Info(params))
+    )
+);
+
+///-------------------------------------------------------------------------------------------------
+named!(pub bestmove_response<UciResponse>,
+    do_parse!(
+        tag!("bestmove ") >>
+        mv: super::uci >>
+        ponder: opt!(complete!(preceded!(tag!(" ponder "), super::uci))) >>
+        (UciResponse::BestMove{ mv: mv, ponder: ponder })
+    )
+);
+
+///-------------------------------------------------------------------------------------------------
+named!(pub id_response<UciResponse>,
+    alt_complete!(
+        map!(preceded!(tag!("id name "), is_not!("\n")), |n| UciResponse::IdName(n)) |
+        map!(preceded!(tag!("id author "), is_not!("\n")), |n| UciResponse::IdAuthor(n))
+    )
+);
+
+///-------------------------------------------------------------------------------------------------
+named!(pub option_response<UciResponse>,
+    do_parse!(
+        tag!("option name ") >>
+        parsed: alt_complete!(
+            do_parse!(
+                name: take_until!(" type ") >>
+                rest: is_not!("\n") >>
+                (name, rest)
+            ) |
+            map!(is_not!("\n"), |n| (n, &b""[..]))
+        ) >>
+        ({
+            let (name, rest) = parsed;
+            UciResponse::Option{ name: name, rest: rest }
+        })
+    )
+);
+
+///-------------------------------------------------------------------------------------------------
+named!(pub uci_response<UciResponse>,
+    alt_complete!(
+        id_response |
+        map!(tag!("uciok"), |_| UciResponse::UciOk) |
+        map!(tag!("readyok"), |_| UciResponse::ReadyOk) |
+        bestmove_response |
+        info_response |
+        option_response
+    )
+);
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use nom::IResult::*;
+
+    #[test]
+    fn test_simple_commands() {
+        assert_eq!(Done(&b""[..], UciCommand::Uci), uci_command(b"uci"));
+        assert_eq!(Done(&b""[..], UciCommand::IsReady), uci_command(b"isready"));
+        assert_eq!(Done(&b""[..], UciCommand::UciNewGame), uci_command(b"ucinewgame"));
+        assert_eq!(Done(&b""[..], UciCommand::Stop), uci_command(b"stop"));
+        assert_eq!(Done(&b""[..], UciCommand::PonderHit), uci_command(b"ponderhit"));
+    }
+
+    #[test]
+    fn test_position_startpos() {
+        assert_eq!(
+            Done(&b""[..], UciCommand::Position(PositionRoot::StartPos, vec![])),
+            uci_command(b"position startpos")
+        );
+        assert_eq!(
+            Done(&b""[..], UciCommand::Position(
+                PositionRoot::StartPos,
+                vec![make_move_simple(SQ_E2, SQ_E4), make_move_simple(SQ_E7, SQ_E5)]
+            )),
+            uci_command(b"position startpos moves e2e4 e7e5")
+        );
+    }
+
+    #[test]
+    fn test_position_fen() {
+        let result = uci_command(b"position fen rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 moves e2e4");
+        match result {
+            Done(_, UciCommand::Position(PositionRoot::Fen(nodes), moves)) => {
+                assert!(nodes.contains(&fen::Node::Move(WHITE)));
+                assert_eq!(moves, vec![make_move_simple(SQ_E2, SQ_E4)]);
+            },
+            _ => assert!(false, "Unable to parse position fen command")
+        }
+    }
+
+    #[test]
+    fn test_go_command() {
+        assert_eq!(
+            Done(&b""[..], UciCommand::Go(vec![
+                GoParam::WTime(300000), GoParam::BTime(300000), GoParam::Depth(10)
+            ])),
+            uci_command(b"go wtime 300000 btime 300000 depth 10")
+        );
+        assert_eq!(Done(&b""[..], UciCommand::Go(vec![GoParam::Infinite])), uci_command(b"go infinite"));
+    }
+
+    #[test]
+    fn test_setoption_command() {
+        assert_eq!(
+            Done(&b""[..], UciCommand::SetOption{ name: &b"Hash"[..], value: Some(&b"128"[..]) }),
+            uci_command(b"setoption name Hash value 128")
+        );
+        assert_eq!(
+            Done(&b""[..], UciCommand::SetOption{ name: &b"Ponder"[..], value: None }),
+            uci_command(b"setoption name Ponder")
+        );
+    }
+
+    #[test]
+    fn test_id_response() {
+        assert_eq!(Done(&b""[..], UciResponse::IdName(&b"samson 1.0"[..])), uci_response(b"id name samson 1.0"));
+        assert_eq!(Done(&b""[..], UciResponse::IdAuthor(&b"Lakin Wecker"[..])), uci_response(b"id author Lakin Wecker"));
+    }
+
+    #[test]
+    fn test_uciok_readyok() {
+        assert_eq!(Done(&b""[..], UciResponse::UciOk), uci_response(b"uciok"));
+        assert_eq!(Done(&b""[..], UciResponse::ReadyOk), uci_response(b"readyok"));
+    }
+
+    #[test]
+    fn test_bestmove_response() {
+        assert_eq!(
+            Done(&b""[..], UciResponse::BestMove{ mv: make_move_simple(SQ_E2, SQ_E4), ponder: None }),
+            uci_response(b"bestmove e2e4")
+        );
+        assert_eq!(
+            Done(&b""[..], UciResponse::BestMove{
+                mv: make_move_simple(SQ_E2, SQ_E4), ponder: Some(make_move_simple(SQ_E7, SQ_E5))
+            }),
+            uci_response(b"bestmove e2e4 ponder e7e5")
+        );
+    }
+
+    #[test]
+    fn test_info_response() {
+        assert_eq!(
+            Done(&b""[..], UciResponse::Info(vec![
+                InfoParam::Depth(12),
+                InfoParam::Score(Score::Centipawns(34)),
+                InfoParam::Nodes(123456),
+                InfoParam::Pv(vec![make_move_simple(SQ_E2, SQ_E4), make_move_simple(SQ_E7, SQ_E5)])
+            ])),
+            uci_response(b"info depth 12 score cp 34 nodes 123456 pv e2e4 e7e5")
+        );
+        assert_eq!(
+            Done(&b""[..], UciResponse::Info(vec![InfoParam::Score(Score::Mate(-3))])),
+            uci_response(b"info score mate -3")
+        );
+    }
+}
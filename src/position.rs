@@ -1,25 +1,57 @@
 // samson - An engine focused on teaching humans.
-// 
+//
 // Copyright (C) 2004-2008 Tord Romstad (Glaurung author)
 // Copyright (C) 2008-2015 Marco Costalba, Joona Kiiski, Tord Romstad (Stockfish Authors)
 // Copyright (C) 2015-2017 Marco Costalba, Joona Kiiski, Gary Linscott, Tord Romstad (Stockfish Authors)
-// Copyright (C) 2017 Lakin Wecker 
-// 
+// Copyright (C) 2017 Lakin Wecker
+//
 // This program is free software: you can redistribute it and/or modify
 // it under the terms of the GNU General Public License as published by
 // the Free Software Foundation, either version 3 of the License, or
 // (at your option) any later version.
-// 
+//
 // This program is distributed in the hope that it will be useful,
 // but WITHOUT ANY WARRANTY; without even the implied warranty of
 // MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
 // GNU General Public License for more details.
-// 
+//
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 use types::*;
+use eval;
+use material;
+use bitboard;
+use zobrist;
 
-struct StateInfo {
+///-----------------------------------------------------------------------------
+/// Why a FEN string was rejected by `Position::set`. Carries enough detail
+/// to point a caller at the offending field instead of just panicking.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FenError {
+    /// Wrong number of `/`-separated ranks (want 8).
+    WrongRankCount(usize),
+    /// A rank's square count didn't add up to 8. `n` is the rank's
+    /// 0-based position in the FEN, counting from the top of the board.
+    MalformedRank(usize),
+    /// A piece-placement field held a character that isn't a digit, `/`,
+    /// or one of `pnbrqkPNBRQK`.
+    BadPieceChar(char),
+    /// The side-to-move field wasn't `w` or `b`.
+    BadSideToMove,
+    /// The castling-rights field held a character that isn't `-`, one of
+    /// `KQkq`, or (Chess960) a rook file letter.
+    BadCastlingField(char),
+    /// The en-passant field wasn't `-` or a valid algebraic square.
+    BadEnPassantSquare,
+    /// The halfmove-clock or fullmove-number field wasn't a plain integer.
+    BadMoveCounter,
+    /// Fewer than four space-separated fields were present.
+    TooFewFields,
+    /// `Color` didn't have exactly one king on the board.
+    BadKingCount(Color),
+}
+
+pub struct StateInfo {
     // Copied when making a move
     pawn_key: Key,
     material_key: Key,
@@ -30,6 +62,14 @@ struct StateInfo {
     psq: Score,
     ep_square: Square,
 
+    // Set by `do_move`: 0 when this position has not occurred before in
+    // the current search line, a positive ply-distance when it repeats a
+    // position that itself was not a repetition, or the negation of that
+    // distance when it repeats a position that *was* already a
+    // repetition (a "double repetition", which `has_game_cycle` treats
+    // as an unavoidable draw regardless of search depth).
+    repetition: i32,
+
     // Not copied when making a move (will be recomputed anyhow)
     key: Key,
     checkers_bb: Bitboard,
@@ -37,19 +77,74 @@ struct StateInfo {
     previous: Option<Box<StateInfo>>,
     blockers_for_king: [Bitboard; COLOR_NB_USIZE],
     pinners_for_king: [Bitboard; COLOR_NB_USIZE],
-    check_squares: [Bitboard; PIECE_TYPE_NB_USIZE]
+    check_squares: [Bitboard; PIECE_TYPE_NB_USIZE],
+
+    // NNUE-style incremental evaluation state. `dirty_piece` records what
+    // changed to reach this node from `previous`; `accumulator` is only
+    // valid once `ensure_accumulator` has replayed the dirty pieces
+    // forward from the nearest computed ancestor.
+    dirty_piece: eval::DirtyPiece,
+    accumulator: eval::Accumulator,
+}
+
+impl StateInfo {
+    fn root() -> StateInfo {
+        StateInfo {
+            pawn_key: Key(0),
+            material_key: Key(0),
+            non_pawn_material: [VALUE_ZERO; COLOR_NB_USIZE],
+            castling_rights: 0,
+            rule50: 0,
+            plies_from_null: 0,
+            psq: SCORE_ZERO,
+            ep_square: SQ_NONE,
+            repetition: 0,
+            key: Key(0),
+            checkers_bb: Bitboard(0),
+            captured_piece: NO_PIECE,
+            previous: None,
+            blockers_for_king: [Bitboard(0); COLOR_NB_USIZE],
+            pinners_for_king: [Bitboard(0); COLOR_NB_USIZE],
+            check_squares: [Bitboard(0); PIECE_TYPE_NB_USIZE],
+            dirty_piece: eval::DIRTY_PIECE_NONE,
+            accumulator: eval::ACCUMULATOR_EMPTY,
+        }
+    }
+}
+
+impl StateInfo {
+    /// Walk this state and each of its ancestors, most recent first, all
+    /// the way back to the root `StateInfo`.
+    pub fn ancestors(&self) -> Ancestors {
+        Ancestors { cur: Some(self) }
+    }
+}
+
+pub struct Ancestors<'a> {
+    cur: Option<&'a StateInfo>,
+}
+
+impl<'a> Iterator for Ancestors<'a> {
+    type Item = &'a StateInfo;
+    fn next(&mut self) -> Option<&'a StateInfo> {
+        let st = self.cur.take();
+        if let Some(st) = st {
+            self.cur = st.previous.as_ref().map(|b| &**b);
+        }
+        st
+    }
 }
 
 // TODO: Figure out how this is used.
 // typedef std::unique_ptr<std::deque<StateInfo>> StateListPtr;
 
-struct Position {
+pub struct Position {
   // Data members
   board: [Piece; SQUARE_NB_USIZE],
   by_type_bb: [Bitboard; PIECE_TYPE_NB_USIZE],
   by_color_bb: [Bitboard; COLOR_NB_USIZE],
   piece_count: [i32; PIECE_NB_USIZE],
-  piece_list: [[Square; PIECE_NB_USIZE]; 16],
+  piece_list: [[Square; 16]; PIECE_NB_USIZE],
   index: [i32; SQUARE_NB_USIZE],
   castling_rights_mask: [i32;  SQUARE_NB_USIZE],
   castling_rook_square: [Square; CASTLING_RIGHT_NB_USIZE],
@@ -61,3 +156,1283 @@ struct Position {
   st: Option<Box<StateInfo>>,
   chess960: bool
 }
+
+impl Position {
+    /// Build a `Position` from Forsyth-Edwards Notation. The castling-rights
+    /// field may use plain `KQkq` or rook-file letters (Shredder-FEN/X-FEN
+    /// style) whatever `chess960` says; the flag only picks the style
+    /// `to_fen` writes castling rights back out in.
+    pub fn set(fen: &str, chess960: bool) -> Result<Position, FenError> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if fields.len() < 4 {
+            return Err(FenError::TooFewFields);
+        }
+
+        let mut pos = Position {
+            board: [NO_PIECE; SQUARE_NB_USIZE],
+            by_type_bb: [Bitboard(0); PIECE_TYPE_NB_USIZE],
+            by_color_bb: [Bitboard(0); COLOR_NB_USIZE],
+            piece_count: [0; PIECE_NB_USIZE],
+            piece_list: [[SQ_NONE; 16]; PIECE_NB_USIZE],
+            index: [0; SQUARE_NB_USIZE],
+            castling_rights_mask: [0; SQUARE_NB_USIZE],
+            castling_rook_square: [SQ_NONE; CASTLING_RIGHT_NB_USIZE],
+            castling_path: [Bitboard(0); CASTLING_RIGHT_NB_USIZE],
+            nodes: 0,
+            game_ply: 0,
+            side_to_move: WHITE,
+            st: Some(Box::new(StateInfo::root())),
+            chess960: chess960,
+        };
+
+        // 1. Piece placement, ranks 8 down to 1.
+        let ranks: Vec<&str> = fields[0].split('/').collect();
+        if ranks.len() != 8 {
+            return Err(FenError::WrongRankCount(ranks.len()));
+        }
+        for (rank_index, rank_str) in ranks.iter().enumerate() {
+            let rank = RANK_8 - Rank(rank_index as i8);
+            let mut file = FILE_A;
+            for c in rank_str.chars() {
+                if file > FILE_H {
+                    return Err(FenError::MalformedRank(rank_index));
+                }
+                if let Some(skip) = c.to_digit(10) {
+                    file = File(file.0 + skip as i8);
+                    continue;
+                }
+                let pc = match c {
+                    'P' => W_PAWN, 'N' => W_KNIGHT, 'B' => W_BISHOP,
+                    'R' => W_ROOK, 'Q' => W_QUEEN, 'K' => W_KING,
+                    'p' => B_PAWN, 'n' => B_KNIGHT, 'b' => B_BISHOP,
+                    'r' => B_ROOK, 'q' => B_QUEEN, 'k' => B_KING,
+                    _ => return Err(FenError::BadPieceChar(c)),
+                };
+                pos.put_piece(pc, make_square(file, rank));
+                file = File(file.0 + 1);
+            }
+            if file != FILE_NB {
+                return Err(FenError::MalformedRank(rank_index));
+            }
+        }
+
+        for &color in &[WHITE, BLACK] {
+            if pos.piece_count[make_piece(color, KING).0 as usize] != 1 {
+                return Err(FenError::BadKingCount(color));
+            }
+        }
+
+        // 2. Side to move.
+        pos.side_to_move = match fields[1] {
+            "w" => WHITE,
+            "b" => BLACK,
+            _ => return Err(FenError::BadSideToMove),
+        };
+
+        // 3. Castling rights, standard `KQkq` or Chess960 rook-file letters.
+        if fields[2] != "-" {
+            for c in fields[2].chars() {
+                let (color, rook_file) = match c {
+                    'K' => (WHITE, pos.furthest_rook_file(WHITE, FILE_H)),
+                    'Q' => (WHITE, pos.furthest_rook_file(WHITE, FILE_A)),
+                    'k' => (BLACK, pos.furthest_rook_file(BLACK, FILE_H)),
+                    'q' => (BLACK, pos.furthest_rook_file(BLACK, FILE_A)),
+                    'A'...'H' => (WHITE, File(c as i8 - 'A' as i8)),
+                    'a'...'h' => (BLACK, File(c as i8 - 'a' as i8)),
+                    _ => return Err(FenError::BadCastlingField(c)),
+                };
+                let rook_square = make_square(rook_file, relative_rank(color, RANK_1));
+                pos.set_castling_right(color, rook_square);
+            }
+        }
+
+        // 4. En passant target square.
+        if fields.len() > 3 && fields[3] != "-" {
+            let bytes = fields[3].as_bytes();
+            if bytes.len() != 2 {
+                return Err(FenError::BadEnPassantSquare);
+            }
+            let file = bytes[0] as i8 - b'a' as i8;
+            let rank = bytes[1] as i8 - b'1' as i8;
+            if file < FILE_A.0 || file > FILE_H.0 || rank < RANK_1.0 || rank > RANK_8.0 {
+                return Err(FenError::BadEnPassantSquare);
+            }
+            let ep = make_square(File(file), Rank(rank));
+            if let Some(ref mut st) = pos.st {
+                st.ep_square = ep;
+            }
+        }
+
+        // 5. Halfmove clock and 6. fullmove number, both optional.
+        let rule50 = match fields.get(4) {
+            Some(s) => s.parse::<i32>().map_err(|_| FenError::BadMoveCounter)?,
+            None => 0,
+        };
+        let fullmove = match fields.get(5) {
+            Some(s) => s.parse::<u32>().map_err(|_| FenError::BadMoveCounter)?,
+            None => 1,
+        };
+        if let Some(ref mut st) = pos.st {
+            st.rule50 = rule50;
+        }
+        pos.game_ply = 2 * fullmove.saturating_sub(1) + if pos.side_to_move == BLACK { 1u32 } else { 0u32 };
+
+        pos.compute_non_pawn_material();
+        pos.compute_material_key();
+
+        let ep_square = match pos.st {
+            Some(ref st) => st.ep_square,
+            None => SQ_NONE,
+        };
+        let key = zobrist::compute(&pos.board, pos.side_to_move, pos.castling_rights(), ep_square);
+        let pawn_key = zobrist::pawn_key(&pos.board);
+        if let Some(ref mut st) = pos.st {
+            st.key = key;
+            st.pawn_key = pawn_key;
+        }
+        pos.set_check_info();
+
+        Ok(pos)
+    }
+
+    /// Serialize back out to FEN, writing castling rights in Shredder
+    /// style (rook-file letters) only if this `Position` was itself built
+    /// in Chess960 mode -- otherwise the standard `KQkq` letters. Round-
+    /// trips with `set` for any position this crate can itself produce.
+    pub fn to_fen(&self) -> String {
+        self.fen_with_castling_style(self.chess960)
+    }
+
+    /// Serialize back out to FEN, always writing castling rights as
+    /// rook-file letters (Shredder-FEN/X-FEN), regardless of whether this
+    /// `Position` was itself parsed in Chess960 mode. Also round-trips
+    /// with `set`, since `set` accepts file-letter castling rights
+    /// unconditionally.
+    pub fn to_shredder_fen(&self) -> String {
+        self.fen_with_castling_style(true)
+    }
+
+    fn fen_with_castling_style(&self, shredder: bool) -> String {
+        let mut s = String::new();
+        for rank_index in 0..8 {
+            let rank = RANK_8 - Rank(rank_index);
+            if rank_index > 0 {
+                s.push('/');
+            }
+            let mut empty = 0;
+            for file in FILES {
+                let pc = self.board[make_square(*file, rank).0 as usize];
+                if pc == NO_PIECE {
+                    empty += 1;
+                    continue;
+                }
+                if empty > 0 {
+                    s.push_str(&empty.to_string());
+                    empty = 0;
+                }
+                s.push(Position::piece_char(pc));
+            }
+            if empty > 0 {
+                s.push_str(&empty.to_string());
+            }
+        }
+
+        s.push(' ');
+        s.push(if self.side_to_move == WHITE { 'w' } else { 'b' });
+
+        s.push(' ');
+        let castling = self.castling_rights_field(shredder);
+        s.push_str(if castling.is_empty() { "-" } else { &castling });
+
+        s.push(' ');
+        let ep = match self.st {
+            Some(ref st) if st.ep_square != SQ_NONE => {
+                let f = (b'a' + file_of(st.ep_square).0 as u8) as char;
+                let r = (b'1' + rank_of(st.ep_square).0 as u8) as char;
+                format!("{}{}", f, r)
+            }
+            _ => "-".to_string(),
+        };
+        s.push_str(&ep);
+
+        let rule50 = match self.st {
+            Some(ref st) => st.rule50,
+            None => 0,
+        };
+        s.push_str(&format!(" {} {}", rule50, self.game_ply / 2 + 1));
+        s
+    }
+
+    fn piece_char(pc: Piece) -> char {
+        let letters = ['p', 'n', 'b', 'r', 'q', 'k'];
+        let letter = letters[(type_of_piece(pc).0 - 1) as usize];
+        if color_of(pc) == WHITE { letter.to_ascii_uppercase() } else { letter }
+    }
+
+    fn castling_rights_field(&self, shredder: bool) -> String {
+        let mut s = String::new();
+        let castling_rights = self.castling_rights();
+        if !shredder {
+            if castling_rights & WHITE_OO.0 as i32 != 0 { s.push('K'); }
+            if castling_rights & WHITE_OOO.0 as i32 != 0 { s.push('Q'); }
+            if castling_rights & BLACK_OO.0 as i32 != 0 { s.push('k'); }
+            if castling_rights & BLACK_OOO.0 as i32 != 0 { s.push('q'); }
+        } else {
+            for &(right, color) in &[(WHITE_OO, WHITE), (WHITE_OOO, WHITE), (BLACK_OO, BLACK), (BLACK_OOO, BLACK)] {
+                if castling_rights & right.0 as i32 == 0 {
+                    continue;
+                }
+                let rook_square = self.castling_rook_square[right.0 as usize];
+                let letter = (b'a' + file_of(rook_square).0 as u8) as char;
+                s.push(if color == WHITE { letter.to_ascii_uppercase() } else { letter });
+            }
+        }
+        s
+    }
+
+    /// Put `pc` on `sq` while `set` is still assembling the board: update
+    /// `board`, both bitboard planes, and the `piece_list`/`index` pair.
+    fn put_piece(&mut self, pc: Piece, sq: Square) {
+        self.board[sq.0 as usize] = pc;
+        let bb = Bitboard(1u64 << (sq.0 as u64));
+        self.by_type_bb[ALL_PIECES.0 as usize] |= bb;
+        self.by_type_bb[type_of_piece(pc).0 as usize] |= bb;
+        self.by_color_bb[color_of(pc).0 as usize] |= bb;
+        let n = self.piece_count[pc.0 as usize] as usize;
+        self.piece_list[pc.0 as usize][n] = sq;
+        self.index[sq.0 as usize] = n as i32;
+        self.piece_count[pc.0 as usize] += 1;
+    }
+
+    /// The file of the rook furthest towards `toward_file` on `color`'s
+    /// back rank, for interpreting the standard (non-Chess960) `KQkq`
+    /// castling letters once more than one rook could plausibly qualify.
+    fn furthest_rook_file(&self, color: Color, toward_file: File) -> File {
+        let back_rank = relative_rank(color, RANK_1);
+        let rook = make_piece(color, ROOK);
+        let files = if toward_file == FILE_H {
+            (0..8).rev().collect::<Vec<i8>>()
+        } else {
+            (0..8).collect::<Vec<i8>>()
+        };
+        for f in files {
+            let sq = make_square(File(f), back_rank);
+            if self.board[sq.0 as usize] == rook {
+                return File(f);
+            }
+        }
+        toward_file
+    }
+
+    /// Record that `color` may still castle with the rook on
+    /// `rook_square`, following Stockfish's `set_castling_right`: derives
+    /// the side from which side of the king the rook sits on, then fills
+    /// in the rights mask, rook square, and castling path for that right.
+    fn set_castling_right(&mut self, color: Color, rook_square: Square) {
+        let king_square = self.king_square(color);
+        let king_side = rook_square > king_square;
+        let side = if king_side { KING_SIDE } else { QUEEN_SIDE };
+        let cr = side | color;
+
+        if let Some(ref mut st) = self.st {
+            st.castling_rights |= cr.0 as i32;
+        }
+        self.castling_rights_mask[king_square.0 as usize] |= cr.0 as i32;
+        self.castling_rights_mask[rook_square.0 as usize] |= cr.0 as i32;
+        self.castling_rook_square[cr.0 as usize] = rook_square;
+
+        let king_to = make_square(if king_side { FILE_G } else { FILE_C }, relative_rank(color, RANK_1));
+        let rook_to = make_square(if king_side { FILE_F } else { FILE_D }, relative_rank(color, RANK_1));
+
+        let mut path = Bitboard(0);
+        let lo = ::std::cmp::min(rook_square, rook_to).0;
+        let hi = ::std::cmp::max(rook_square, rook_to).0;
+        for s in lo..(hi + 1) {
+            if Square(s) != king_square && Square(s) != rook_square {
+                path |= Bitboard(1u64 << (s as u64));
+            }
+        }
+        let lo = ::std::cmp::min(king_square, king_to).0;
+        let hi = ::std::cmp::max(king_square, king_to).0;
+        for s in lo..(hi + 1) {
+            if Square(s) != king_square && Square(s) != rook_square {
+                path |= Bitboard(1u64 << (s as u64));
+            }
+        }
+        self.castling_path[cr.0 as usize] = path;
+    }
+
+    /// Fill `non_pawn_material` from the piece counts just assembled by
+    /// `set`, using the midgame piece values (`KNIGHT_VALUE_MG` through
+    /// `QUEEN_VALUE_MG`) from `types`. `psq` is not computed here.
+    fn compute_non_pawn_material(&mut self) {
+        let mut total = [VALUE_ZERO; COLOR_NB_USIZE];
+        for &color in &[WHITE, BLACK] {
+            let mut sum = VALUE_ZERO;
+            for &(pt, value) in &[
+                (KNIGHT, KNIGHT_VALUE_MG), (BISHOP, BISHOP_VALUE_MG),
+                (ROOK, ROOK_VALUE_MG), (QUEEN, QUEEN_VALUE_MG),
+            ] {
+                let pc = make_piece(color, pt);
+                sum += value.0 * self.piece_count[pc.0 as usize];
+            }
+            total[color.0 as usize] = sum;
+        }
+        if let Some(ref mut st) = self.st {
+            st.non_pawn_material = total;
+        }
+    }
+
+    /// An order-independent hash of the piece counts, keying the
+    /// material-entry cache: `zobrist::material` reuses the piece-square
+    /// table's rows as a piece-count index, so this is just as real a
+    /// Zobrist hash as `key` itself, only indexed by count instead of
+    /// square.
+    fn compute_material_key(&mut self) {
+        let mut key = Key(0);
+        for pc in 0..PIECE_NB_USIZE {
+            key = key ^ zobrist::material(Piece(pc as i8), self.piece_count[pc]);
+        }
+        if let Some(ref mut st) = self.st {
+            st.material_key = key;
+        }
+    }
+
+    /// Every one of `sliders`' pieces that would attack `s` if nothing
+    /// stood between them, following Stockfish's `slider_blockers`: find
+    /// the "snipers" -- `sliders`' rooks/bishops/queens whose empty-board
+    /// ray reaches `s` -- against the real board with the snipers
+    /// themselves removed (so a sniper behind another sniper on the same
+    /// ray still counts), then for each sniper whose ray to `s` crosses
+    /// exactly one occupied square, that square is a blocker, and the
+    /// sniper is one of its pinners if the blocker is the same color as
+    /// whatever sits on `s`.
+    fn slider_blockers(&self, sliders: Bitboard, s: Square) -> (Bitboard, Bitboard) {
+        let mut blockers = Bitboard(0);
+        let mut pinners = Bitboard(0);
+
+        let rooks_and_queens = self.by_type_bb[ROOK.0 as usize] | self.by_type_bb[QUEEN.0 as usize];
+        let bishops_and_queens = self.by_type_bb[BISHOP.0 as usize] | self.by_type_bb[QUEEN.0 as usize];
+        let mut snipers = ((bitboard::attacks_bb(ROOK, s, Bitboard(0)) & rooks_and_queens)
+            | (bitboard::attacks_bb(BISHOP, s, Bitboard(0)) & bishops_and_queens)) & sliders;
+        let occupancy = self.occupied() & !snipers;
+
+        while !snipers.is_empty() {
+            let sniper_sq = snipers.pop_lsb();
+            let b = bitboard::between(s, sniper_sq) & occupancy;
+            if !b.is_empty() && !bitboard::more_than_one(b) {
+                blockers |= b;
+                if !(b & self.pieces(color_of(self.piece_on(s)), ALL_PIECES)).is_empty() {
+                    pinners |= Bitboard::from_square(sniper_sq);
+                }
+            }
+        }
+        (blockers, pinners)
+    }
+
+    /// Populate `checkers_bb`, `blockers_for_king`/`pinners_for_king` (for
+    /// both colors), and `check_squares`, following Stockfish's
+    /// `set_check_info`. Computed once, from the fully-assembled board, at
+    /// the end of `set` -- `do_move` doesn't maintain `by_type_bb`/
+    /// `piece_list` incrementally yet, so it doesn't call this; these
+    /// fields describe the position `set` built, not whatever `do_move`
+    /// has been applied since.
+    fn set_check_info(&mut self) {
+        let us = self.side_to_move;
+        let them = -us;
+        let our_king = self.king_square(us);
+        let their_king = self.king_square(them);
+        let occupied = self.occupied();
+
+        let (white_blockers, white_pinners) = self.slider_blockers(self.pieces(BLACK, ALL_PIECES), self.king_square(WHITE));
+        let (black_blockers, black_pinners) = self.slider_blockers(self.pieces(WHITE, ALL_PIECES), self.king_square(BLACK));
+
+        let mut check_squares = [Bitboard(0); PIECE_TYPE_NB_USIZE];
+        check_squares[PAWN.0 as usize] = bitboard::pawn_captures(them, their_king);
+        check_squares[KNIGHT.0 as usize] = bitboard::knight_attacks_from(their_king);
+        check_squares[BISHOP.0 as usize] = bitboard::attacks_bb(BISHOP, their_king, occupied);
+        check_squares[ROOK.0 as usize] = bitboard::attacks_bb(ROOK, their_king, occupied);
+        check_squares[QUEEN.0 as usize] = check_squares[BISHOP.0 as usize] | check_squares[ROOK.0 as usize];
+
+        let checkers = self.attackers_to(our_king, occupied) & self.pieces(them, ALL_PIECES);
+
+        if let Some(ref mut st) = self.st {
+            st.blockers_for_king[WHITE.0 as usize] = white_blockers;
+            st.blockers_for_king[BLACK.0 as usize] = black_blockers;
+            st.pinners_for_king[WHITE.0 as usize] = white_pinners;
+            st.pinners_for_king[BLACK.0 as usize] = black_pinners;
+            st.check_squares = check_squares;
+            st.checkers_bb = checkers;
+        }
+    }
+
+    /// Piece counts for `c`, laid out the way `material::imbalance` wants
+    /// them: the bishop pair first, then pawn/knight/bishop/rook/queen.
+    fn material_counts(&self, c: Color) -> [i32; material::PIECE_KINDS] {
+        let mut counts = [0; material::PIECE_KINDS];
+        for &pt in &[PAWN, KNIGHT, BISHOP, ROOK, QUEEN] {
+            let pc = make_piece(c, pt);
+            counts[material::piece_kind_index(pt)] = self.piece_count[pc.0 as usize];
+        }
+        counts[material::BISHOP_PAIR] = if counts[material::piece_kind_index(BISHOP)] >= 2 { 1 } else { 0 };
+        counts
+    }
+
+    /// Non-linear material-imbalance bonus for the side to move, served
+    /// from the shared material-entry cache keyed on `material_key`
+    /// together with the side to move.
+    pub fn imbalance(&self) -> Score {
+        let material_key = match self.st {
+            Some(ref st) => st.material_key,
+            None => return SCORE_ZERO,
+        };
+        let us = self.side_to_move;
+        let them = -us;
+        let own = self.material_counts(us);
+        let their = self.material_counts(them);
+        let key = if us == BLACK { material_key ^ zobrist::side_to_move() } else { material_key };
+        material::probe(key, &own, &their)
+    }
+
+    /// The square the `c` king sits on, as recorded in the piece list,
+    /// which `set` fills and `do_move` does not update. Used (publicly)
+    /// by SAN move resolution.
+    pub fn king_square(&self, c: Color) -> Square {
+        self.piece_list[make_piece(c, KING).0 as usize][0]
+    }
+
+    /// The piece on `sq`, or `NO_PIECE` if it's empty.
+    pub fn piece_on(&self, sq: Square) -> Piece {
+        self.board[sq.0 as usize]
+    }
+
+    /// Every square occupied by any piece.
+    pub fn occupied(&self) -> Bitboard {
+        self.by_type_bb[ALL_PIECES.0 as usize]
+    }
+
+    /// Every square occupied by one of `c`'s `pt`s.
+    pub fn pieces(&self, c: Color, pt: PieceType) -> Bitboard {
+        self.by_type_bb[pt.0 as usize] & self.by_color_bb[c.0 as usize]
+    }
+
+    /// Whose turn it is to move.
+    pub fn side_to_move(&self) -> Color {
+        self.side_to_move
+    }
+
+    /// The Zobrist key for the current node, as seeded by `set` and kept
+    /// up to date incrementally by `do_move`.
+    pub fn key(&self) -> Key {
+        match self.st {
+            Some(ref st) => st.key,
+            None => Key(0),
+        }
+    }
+
+    /// The en passant target square, or `SQ_NONE` if the last move wasn't
+    /// a two-square pawn push.
+    pub fn ep_square(&self) -> Square {
+        match self.st {
+            Some(ref st) => st.ep_square,
+            None => SQ_NONE,
+        }
+    }
+
+    /// The current node's castling-rights mask, carried in `StateInfo`
+    /// (like `rule50`) rather than `Position` itself, since it's meant to
+    /// be copied forward on `do_move` and restored on `undo_move`.
+    fn castling_rights(&self) -> i32 {
+        match self.st {
+            Some(ref st) => st.castling_rights,
+            None => 0,
+        }
+    }
+
+    /// Does the side named in `cr` still hold that castling right?
+    pub fn can_castle(&self, cr: CastlingRight) -> bool {
+        self.castling_rights() & cr.0 as i32 != 0
+    }
+
+    /// The square the rook paired with `cr` starts on, as recorded by
+    /// `set` (Chess960-aware). Unlike `types::castling_rook_square`, which
+    /// gives the rook's destination square in standard castling.
+    pub fn castling_rook_square(&self, cr: CastlingRight) -> Square {
+        self.castling_rook_square[cr.0 as usize]
+    }
+
+    /// The squares (excluding the king and rook themselves) that must be
+    /// empty for `cr`'s castling move to be pseudo-legal.
+    pub fn castling_path(&self, cr: CastlingRight) -> Bitboard {
+        self.castling_path[cr.0 as usize]
+    }
+
+    /// Is `sq` attacked by any `by`-colored piece, given the position as
+    /// it currently stands? Used for castling legality: the king may not
+    /// start, pass through, or land on a square `by` attacks.
+    pub fn is_attacked_by(&self, sq: Square, by: Color) -> bool {
+        self.attacked_by(sq, by, self.occupied(), Bitboard(0), None)
+    }
+
+    /// Would `us`'s king be attacked if `mv` were played? Lets SAN
+    /// resolution filter pseudo-legal candidates down to legal ones
+    /// without a full move generator.
+    pub fn leaves_king_in_check(&self, us: Color, mv: Move) -> bool {
+        let (_, moved_type, _, to, occupied, captured_bb) = self.hypothetical_move(mv);
+        let king_square = if moved_type == KING { to } else { self.king_square(us) };
+        self.attacked_by(king_square, -us, occupied, captured_bb, None)
+    }
+
+    /// Would playing `mv` give check to the opponent, either directly
+    /// from the moved piece's new square or by discovery? Self-contained
+    /// like `leaves_king_in_check`, rather than relying on `check_squares`
+    /// (which `gives_check` reads): `do_move` doesn't recompute
+    /// `check_squares` for the resulting position, so it's only reliable
+    /// against the position `set` last built, not one reached by playing
+    /// moves. Used by the SAN encoder to decide the `+`/`#` suffix.
+    pub fn move_gives_check(&self, mv: Move) -> bool {
+        let (mover, moved_type, from, to, occupied, _) = self.hypothetical_move(mv);
+        let them = -mover;
+        self.attacked_by(self.king_square(them), mover, occupied, Bitboard(0), Some((moved_type, from, to)))
+    }
+
+    /// Is `mv` checkmate: does it give check, and does the opponent have
+    /// no legal reply?
+    pub fn move_gives_checkmate(&self, mv: Move) -> bool {
+        self.move_gives_check(mv) && !self.has_legal_response(mv)
+    }
+
+    /// Does the opponent have any legal reply in the hypothetical
+    /// position after `us` plays `mv`? Castling is never a legal way out
+    /// of check, so replies don't consider it; en passant immediately
+    /// after `mv` isn't considered either, since `ep_square` reflects the
+    /// state before `mv`, not after (a vanishingly rare gap: a double
+    /// pawn push escaping mate only via en passant).
+    fn has_legal_response(&self, mv: Move) -> bool {
+        let (mover, moved_type, moved_from, moved_to, occupied, captured) = self.hypothetical_move(mv);
+        let them = -mover;
+        let their_occupied = self.pieces(them, ALL_PIECES) & !captured;
+        let our_occupied = occupied & !their_occupied;
+
+        for &pt in &[KING, QUEEN, ROOK, BISHOP, KNIGHT, PAWN] {
+            let mut pieces = self.pieces(them, pt) & !captured;
+            while !pieces.is_empty() {
+                let from = pieces.pop_lsb();
+                let targets = if pt == PAWN {
+                    bitboard::pawn_pushes(them, from, occupied) | (bitboard::pawn_captures(them, from) & our_occupied)
+                } else {
+                    attacks_from(pt, them, from, occupied) & !their_occupied
+                };
+                for to in targets {
+                    let captured_here = Bitboard::from_square(to) & our_occupied;
+                    let occupied_after = (occupied & !Bitboard::from_square(from)) | Bitboard::from_square(to);
+                    let king_square = if pt == KING { to } else { self.king_square(them) };
+                    let exclude = captured | captured_here;
+                    if !self.attacked_by(king_square, mover, occupied_after, exclude, Some((moved_type, moved_from, moved_to))) {
+                        return true;
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    /// The board state as if `mv` had already been played, without
+    /// mutating `self` or relying on `do_move` (which doesn't
+    /// special-case castling/promotion/en passant): the mover, the piece
+    /// type and (from, to) of whichever one of the mover's own pieces
+    /// now attacks from a new square -- the rook for castling, since the
+    /// king itself never gives check -- the resulting occupancy, and the
+    /// square (if any) that was captured.
+    fn hypothetical_move(&self, mv: Move) -> (Color, PieceType, Square, Square, Bitboard, Bitboard) {
+        let from = from_square(mv);
+        let to = to_square(mv);
+        let mover = color_of(self.piece_on(from));
+
+        if type_of_move(mv) == CASTLING {
+            let king_side = to > from;
+            let king_to = make_square(if king_side { FILE_G } else { FILE_C }, relative_rank(mover, RANK_1));
+            let rook_to = make_square(if king_side { FILE_F } else { FILE_D }, relative_rank(mover, RANK_1));
+            let vacated = Bitboard::from_square(from) | Bitboard::from_square(to);
+            let occupied = (self.occupied() & !vacated) | Bitboard::from_square(king_to) | Bitboard::from_square(rook_to);
+            return (mover, ROOK, to, rook_to, occupied, Bitboard(0));
+        }
+
+        let moved_type = match type_of_move(mv) {
+            PROMOTION => promotion_type(mv),
+            _ => type_of_piece(self.piece_on(from)),
+        };
+        let captured_square = if type_of_move(mv) == ENPASSANT {
+            make_square(file_of(to), rank_of(from))
+        } else {
+            to
+        };
+
+        let from_bb = Bitboard::from_square(from);
+        let to_bb = Bitboard::from_square(to);
+        let captured_bb = Bitboard::from_square(captured_square);
+        let mut occupied = (self.occupied() & !from_bb) | to_bb;
+        if captured_square != to {
+            occupied &= !captured_bb;
+        }
+
+        (mover, moved_type, from, to, occupied, captured_bb)
+    }
+
+    /// Is `sq` attacked by any `by`-colored piece, given `occupied`?
+    /// Mirrors `parser::fen::is_attacked`'s per-piece attack check, but
+    /// walks `by`'s own piece bitboards instead of scanning every square,
+    /// and takes `occupied`/`exclude` explicitly so a hypothetical board
+    /// (a piece moved, or removed by a capture) can be tested without
+    /// mutating `self`. `moved`, when given, relocates one of `by`'s own
+    /// pieces from its real square to a hypothetical one before the
+    /// attack check -- needed when `by` is the side that just moved, so
+    /// its attacks are read from where it landed rather than where it
+    /// started.
+    fn attacked_by(
+        &self,
+        sq: Square,
+        by: Color,
+        occupied: Bitboard,
+        exclude: Bitboard,
+        moved: Option<(PieceType, Square, Square)>,
+    ) -> bool {
+        for &pt in &[PAWN, KNIGHT, BISHOP, ROOK, QUEEN, KING] {
+            let mut attackers = self.pieces(by, pt);
+            if let Some((moved_type, from, to)) = moved {
+                if moved_type == pt {
+                    attackers = (attackers & !Bitboard::from_square(from)) | Bitboard::from_square(to);
+                }
+            }
+            attackers &= !exclude;
+            while !attackers.is_empty() {
+                let from = attackers.pop_lsb();
+                if (attacks_from(pt, by, from, occupied) & sq) != Bitboard(0) {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Every square occupied (within `occupied`) by a piece of either
+    /// color that attacks `sq`, found by placing each attacking piece
+    /// type on `sq` itself and re-querying its attacks against `occupied`
+    /// -- unlike `attacked_by`, which works forward from each attacker's
+    /// own square and stops at the first hit, this collects every
+    /// attacker at once. Pawns aren't symmetric, so their attackers are found by
+    /// querying the *opposite* color's capture squares from `sq` instead.
+    fn attackers_to(&self, sq: Square, occupied: Bitboard) -> Bitboard {
+        let mut attackers = Bitboard(0);
+        for &color in &[WHITE, BLACK] {
+            attackers |= bitboard::pawn_captures(-color, sq) & self.pieces(color, PAWN);
+            attackers |= bitboard::knight_attacks_from(sq) & self.pieces(color, KNIGHT);
+            attackers |= bitboard::king_attacks_from(sq) & self.pieces(color, KING);
+            attackers |= bitboard::attacks_bb(BISHOP, sq, occupied) & self.pieces(color, BISHOP);
+            attackers |= bitboard::attacks_bb(ROOK, sq, occupied) & self.pieces(color, ROOK);
+            attackers |= bitboard::attacks_bb(QUEEN, sq, occupied) & self.pieces(color, QUEEN);
+        }
+        attackers & occupied
+    }
+
+    /// Static Exchange Evaluation for the capture `mv`: the net material
+    /// gain for the side making `mv` after both sides trade down on the
+    /// destination square as favorably as possible, assuming each side
+    /// always recaptures with its least valuable attacker. Follows the
+    /// classic swap-off algorithm: seed a `gain` stack with the value of
+    /// the piece initially captured, then repeatedly let the side to move
+    /// recapture with its cheapest attacker (re-deriving attackers from
+    /// `attackers_to` each step so sliding pieces unmasked by a removed
+    /// piece -- x-rays -- are picked up), pushing `gain[d] =
+    /// value(attacker) - gain[d-1]` each time. Once no attacker remains,
+    /// negamax the stack back down so `gain[0]` reflects optimal play by
+    /// both sides. A non-capturing move (including castling) is worth
+    /// nothing to SEE and short-circuits to 0. A king is only let onto
+    /// the exchange if the other side has no attacker left to answer it
+    /// with -- a king can't be "recaptured" like any other piece, so an
+    /// unsafe king capture simply ends the exchange instead.
+    pub fn see(&self, mv: Move) -> i32 {
+        let from = from_square(mv);
+        let to = to_square(mv);
+        let move_type = type_of_move(mv);
+        if move_type == CASTLING || self.piece_on(to) == NO_PIECE && move_type != ENPASSANT {
+            return 0;
+        }
+
+        let mover = color_of(self.piece_on(from));
+        let captured_pt = if move_type == ENPASSANT { PAWN } else { type_of_piece(self.piece_on(to)) };
+
+        let mut occupied = self.occupied() & !Bitboard::from_square(from);
+        if move_type == ENPASSANT {
+            occupied &= !Bitboard::from_square(make_square(file_of(to), rank_of(from)));
+        }
+
+        let mut gain = vec![piece_type_value(MG, captured_pt).0];
+        let mut moved_pt = if move_type == PROMOTION { promotion_type(mv) } else { type_of_piece(self.piece_on(from)) };
+        let mut stm = -mover;
+
+        loop {
+            let attackers = self.attackers_to(to, occupied);
+            let next_attacker = [PAWN, KNIGHT, BISHOP, ROOK, QUEEN, KING].iter()
+                .map(|&pt| (pt, attackers & self.pieces(stm, pt)))
+                .find(|&(_, bb)| !bb.is_empty());
+
+            let (next_pt, candidates) = match next_attacker {
+                Some(found) => found,
+                None => break,
+            };
+
+            // A king may only recapture onto `to` if doing so wouldn't
+            // leave it attacked -- the opposite side can't "recapture the
+            // king" itself, so if it still has an attacker on `to` once
+            // the king is gone, this king capture is unsafe and the
+            // exchange simply stops here instead.
+            if next_pt == KING {
+                let occupied_without_king = occupied & !Bitboard::from_square(candidates.lsb());
+                let opponent_attackers = self.attackers_to(to, occupied_without_king) & self.pieces(-stm, ALL_PIECES);
+                if !opponent_attackers.is_empty() {
+                    break;
+                }
+            }
+
+            let previous_gain = *gain.last().unwrap();
+            gain.push(piece_type_value(MG, moved_pt).0 - previous_gain);
+
+            occupied &= !Bitboard::from_square(candidates.lsb());
+            moved_pt = next_pt;
+            stm = -stm;
+        }
+
+        for d in (1..gain.len()).rev() {
+            let negamax = ::std::cmp::max(-gain[d - 1], gain[d]);
+            gain[d - 1] = -negamax;
+        }
+        gain[0]
+    }
+
+    /// Apply `m`, pushing a new `StateInfo` onto the chain. The new
+    /// node's `DirtyPiece` describes the (piece, from, to) triples that
+    /// changed, but its accumulator is left uncomputed -- callers must
+    /// call `ensure_accumulator` before reading an evaluation for this
+    /// node.
+    pub fn do_move(&mut self, m: Move) {
+        let from = from_square(m);
+        let to = to_square(m);
+        let moved = self.board[from.0 as usize];
+        let captured = self.board[to.0 as usize];
+        let move_type = type_of_move(m);
+
+        let mut dirty = eval::DIRTY_PIECE_NONE;
+        dirty.pc[0] = moved;
+        dirty.from[0] = from;
+        dirty.to[0] = to;
+        dirty.dirty_num = 1;
+
+        if captured != NO_PIECE && move_type != CASTLING {
+            let n = dirty.dirty_num as usize;
+            dirty.pc[n] = captured;
+            dirty.from[n] = to;
+            dirty.to[n] = SQ_NONE;
+            dirty.dirty_num += 1;
+        }
+
+        self.board[to.0 as usize] = moved;
+        self.board[from.0 as usize] = NO_PIECE;
+
+        let (prev_rule50, prev_plies_from_null, prev_key) = match self.st {
+            Some(ref st) => (st.rule50, st.plies_from_null, st.key),
+            None => (0, 0, Key(0)),
+        };
+
+        let mut st = Box::new(StateInfo::root());
+        st.dirty_piece = dirty;
+        st.plies_from_null = prev_plies_from_null + 1;
+        st.rule50 = if type_of_piece(moved) == PAWN || captured != NO_PIECE { 0 } else { prev_rule50 + 1 };
+        // Toggle only the keys this (still partial) mover actually knows
+        // changed: the moved piece leaving `from` and landing on `to`, the
+        // captured piece (if any) disappearing from `to`, and the side to
+        // move flipping. Castling-rights and en-passant-file keys aren't
+        // touched here yet, since `do_move` doesn't maintain those fields
+        // itself.
+        let mut key = prev_key ^ zobrist::piece_square(moved, from) ^ zobrist::piece_square(moved, to);
+        if captured != NO_PIECE && move_type != CASTLING {
+            key = key ^ zobrist::piece_square(captured, to);
+        }
+        key = key ^ zobrist::side_to_move();
+        st.key = key;
+        // A king move invalidates every other feature index for this
+        // perspective, so leave `computed` false here -- the next
+        // `ensure_accumulator` call will notice there is no usable
+        // ancestor chain for a king move and do a full refresh instead.
+        st.previous = self.st.take();
+        self.st = Some(st);
+
+        self.compute_repetition();
+    }
+
+    /// Recompute `st.repetition` for the current node following
+    /// Stockfish's `has_repeated`: walk back two plies at a time starting
+    /// at the grandparent, within the irreversible-move window bounded by
+    /// `rule50`/`plies_from_null`, looking for a state with the same key.
+    fn compute_repetition(&mut self) {
+        let (end, current_key) = match self.st {
+            Some(ref st) => (::std::cmp::min(st.rule50, st.plies_from_null), st.key),
+            None => return,
+        };
+
+        let mut repetition = 0;
+        if end >= 4 {
+            let mut ancestor: Option<&StateInfo> = self.st.as_ref()
+                .and_then(|st| st.previous.as_ref())
+                .and_then(|p| p.previous.as_ref())
+                .map(|b| &**b);
+            let mut i = 4;
+            while i <= end {
+                ancestor = ancestor
+                    .and_then(|a| a.previous.as_ref())
+                    .and_then(|p| p.previous.as_ref())
+                    .map(|b| &**b);
+                match ancestor {
+                    Some(a) if a.key == current_key => {
+                        repetition = if a.repetition != 0 { -i } else { i };
+                        break;
+                    }
+                    Some(_) => {}
+                    None => break,
+                }
+                i += 2;
+            }
+        }
+        if let Some(ref mut st) = self.st {
+            st.repetition = repetition;
+        }
+    }
+
+    /// Is the side to move's king currently in check? `checkers_bb` is
+    /// set by `set_check_info` (called from `set`) and not yet refreshed
+    /// by `do_move`, so this answers for the position last `set`, not one
+    /// reached by playing moves since.
+    fn in_check(&self) -> bool {
+        match self.st {
+            Some(ref st) => st.checkers_bb != Bitboard(0),
+            None => false,
+        }
+    }
+
+    /// True if moving the piece on `s` uncovers a check against `c`'s
+    /// king: `s` must be one of `c`'s pinned pieces (`blockers_for_king`)
+    /// and the move must leave the ray it was pinned along. Like
+    /// `in_check`, only reliable against the position `set` last built.
+    pub fn is_discovered_check_on_king(&self, c: Color, m: Move) -> bool {
+        let st = match self.st {
+            Some(ref st) => st,
+            None => return false,
+        };
+        let from = from_square(m);
+        let to = to_square(m);
+        let king_square = self.king_square(c);
+        bb_contains(st.blockers_for_king[c.0 as usize], from) && !aligned(from, to, king_square)
+    }
+
+    /// True if making `m` gives check to the opponent: either a direct
+    /// check (the destination square is one of `check_squares` for the
+    /// moved piece's type) or a discovered check uncovered by moving a
+    /// pinned piece off its ray. Promotions are tested against the
+    /// promoted piece type; castling is tested against the rook's
+    /// landing square; en passant additionally relies on
+    /// `is_discovered_check_on_king` to catch the (rare) case where the
+    /// capturing pawn itself was pinned. `check_squares` is only current
+    /// for the position `set` last built (see `in_check`); prefer
+    /// `move_gives_check` once any moves have been played since.
+    pub fn gives_check(&self, m: Move) -> bool {
+        let st = match self.st {
+            Some(ref st) => st,
+            None => return false,
+        };
+        let them = -self.side_to_move;
+        if self.is_discovered_check_on_king(them, m) {
+            return true;
+        }
+
+        let from = from_square(m);
+        let to = to_square(m);
+        let moved = self.board[from.0 as usize];
+        match type_of_move(m) {
+            PROMOTION => bb_contains(st.check_squares[promotion_type(m).0 as usize], to),
+            CASTLING => {
+                let king_side = to > from;
+                let rook_to = make_square(
+                    if king_side { FILE_F } else { FILE_D },
+                    relative_rank(self.side_to_move, RANK_1),
+                );
+                bb_contains(st.check_squares[ROOK.0 as usize], rook_to)
+            }
+            _ => bb_contains(st.check_squares[type_of_piece(moved).0 as usize], to),
+        }
+    }
+
+    /// True when the fifty-move rule has expired (and the side to move
+    /// is not checkmated -- approximated here as "not currently in
+    /// check", since this crate has no legal move generator yet to tell
+    /// checkmate from ordinary check), or when the current position is a
+    /// repetition that occurred at or after `ply`.
+    pub fn is_draw(&self, ply: i32) -> bool {
+        let st = match self.st {
+            Some(ref st) => st,
+            None => return false,
+        };
+        if st.rule50 > 99 && !self.in_check() {
+            return true;
+        }
+        st.repetition != 0 && st.repetition < ply
+    }
+
+    /// True when the current node lies on a repetition cycle that closes
+    /// within the current search line (i.e. before `ply`), independent of
+    /// the fifty-move counter. Useful for cycle-aware search pruning.
+    pub fn has_game_cycle(&self, ply: i32) -> bool {
+        match self.st {
+            Some(ref st) => st.repetition != 0 && st.repetition < ply,
+            None => false,
+        }
+    }
+
+    /// Undo the most recently applied move. No inverse accumulator math
+    /// is needed: we simply drop the current `StateInfo` and restore the
+    /// parent, exactly as it was before `do_move`.
+    pub fn undo_move(&mut self) {
+        let st = match self.st.take() {
+            Some(st) => st,
+            None => return,
+        };
+        let dirty = st.dirty_piece;
+        for i in (0..dirty.dirty_num as usize).rev() {
+            if dirty.to[i] != SQ_NONE {
+                self.board[dirty.to[i].0 as usize] = NO_PIECE;
+            }
+            self.board[dirty.from[i].0 as usize] = dirty.pc[i];
+        }
+        self.st = st.previous;
+    }
+
+    /// Make sure the accumulator at the current node is up to date for
+    /// both perspectives, walking back through `previous` to the nearest
+    /// computed ancestor and replaying `DirtyPiece`s forward. Falls back
+    /// to a full refresh when no ancestor in the chain has ever been
+    /// computed (e.g. right after `set`).
+    pub fn ensure_accumulator(&mut self, weights: &eval::FeatureWeights) {
+        for &perspective in &[WHITE, BLACK] {
+            self.ensure_accumulator_for(weights, perspective);
+        }
+    }
+
+    fn ensure_accumulator_for(&mut self, weights: &eval::FeatureWeights, perspective: Color) {
+        let already_computed = match self.st {
+            Some(ref st) => st.accumulator.computed[perspective.0 as usize],
+            None => return,
+        };
+        if already_computed {
+            return;
+        }
+
+        // `do_move` moves pieces on `board` only, so read the king's
+        // square from there rather than from `piece_list`.
+        let king = make_piece(perspective, KING);
+        let king_square = (SQ_A1.0..(SQ_H8.0 + 1)).map(Square).find(|&s| self.board[s.0 as usize] == king).unwrap_or(SQ_NONE);
+        let (mut dirties, ancestor) = {
+            let mut dirties = Vec::new();
+            let mut cur = self.st.as_ref().unwrap();
+            loop {
+                dirties.push(cur.dirty_piece);
+                if cur.accumulator.computed[perspective.0 as usize] {
+                    break (dirties, Some(cur.accumulator));
+                }
+                match cur.previous {
+                    Some(ref prev) => cur = prev,
+                    None => break (dirties, None),
+                }
+            }
+        };
+
+        let mut acc = match ancestor {
+            Some(acc) => acc,
+            None => {
+                // No ancestor has ever been computed: do a full refresh
+                // from the board instead of trying to replay the chain.
+                self.refresh_accumulator_for(weights, perspective);
+                return;
+            }
+        };
+
+        // A king move shifts every other piece's king-relative feature
+        // index, so a partial replay is only valid if `perspective`'s
+        // king didn't move anywhere in the dirty chain being replayed
+        // (everything but the ancestor's own dirty piece, already baked
+        // into `acc`). If it did, fall back to a full board refresh
+        // instead of replaying with a stale `king_square`.
+        let king_moved = dirties[..dirties.len() - 1].iter().any(|dirty| {
+            (0..dirty.dirty_num as usize).any(|i| dirty.pc[i] == make_piece(perspective, KING))
+        });
+        if king_moved {
+            self.refresh_accumulator_for(weights, perspective);
+            return;
+        }
+
+        dirties.pop(); // the ancestor's own dirty piece is already baked into `acc`
+        for dirty in dirties.iter().rev() {
+            eval::update_accumulator(weights, &mut acc, perspective, king_square, dirty);
+        }
+        if let Some(ref mut st) = self.st {
+            st.accumulator.accumulation[perspective.0 as usize] = acc.accumulation[perspective.0 as usize];
+            st.accumulator.computed[perspective.0 as usize] = true;
+        }
+    }
+
+    /// Fully recompute `perspective`'s accumulator straight from the
+    /// board, bypassing the dirty-piece replay. Shared by
+    /// `ensure_accumulator_for`'s two bail-out cases: no computed
+    /// ancestor to replay from, and a king move somewhere in the chain
+    /// that would be replayed.
+    fn refresh_accumulator_for(&mut self, weights: &eval::FeatureWeights, perspective: Color) {
+        let king = make_piece(perspective, KING);
+        let king_square = (SQ_A1.0..(SQ_H8.0 + 1)).map(Square).find(|&s| self.board[s.0 as usize] == king).unwrap_or(SQ_NONE);
+        let mut pieces = Vec::new();
+        for sq in SQ_A1.0..(SQ_H8.0 + 1) {
+            let pc = self.board[sq as usize];
+            if pc != NO_PIECE {
+                pieces.push((pc, Square(sq)));
+            }
+        }
+        let mut fresh = eval::ACCUMULATOR_EMPTY;
+        eval::refresh_accumulator(weights, &mut fresh, perspective, king_square, &pieces);
+        if let Some(ref mut st) = self.st {
+            st.accumulator.accumulation[perspective.0 as usize] = fresh.accumulation[perspective.0 as usize];
+            st.accumulator.computed[perspective.0 as usize] = true;
+        }
+    }
+}
+
+///-----------------------------------------------------------------------------
+fn bb_contains(bb: Bitboard, sq: Square) -> bool {
+    bb & Bitboard(1u64 << (sq.0 as u64)) != Bitboard(0)
+}
+
+/// Where a `color`-colored `pt` on `from` attacks, given `occupied` for
+/// the sliding piece types. Shared by `attacked_by` and
+/// `has_legal_response`.
+fn attacks_from(pt: PieceType, color: Color, from: Square, occupied: Bitboard) -> Bitboard {
+    match pt {
+        PAWN => bitboard::pawn_captures(color, from),
+        KNIGHT => bitboard::knight_attacks_from(from),
+        BISHOP => bitboard::attacks_bb(BISHOP, from, occupied),
+        ROOK => bitboard::attacks_bb(ROOK, from, occupied),
+        QUEEN => bitboard::attacks_bb(QUEEN, from, occupied),
+        KING => bitboard::king_attacks_from(from),
+        _ => Bitboard(0),
+    }
+}
+
+/// True when `a`, `b`, and `c` lie on a common rank, file, or diagonal, in
+/// any order.
+fn aligned(a: Square, b: Square, c: Square) -> bool {
+    a == b || !(bitboard::line(a, b) & c).is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A representative sample of positions -- the standard start, a
+    // middlegame position with every kind of piece still on the board, one
+    // with an en-passant target square, and both sides of a Chess960
+    // castling setup -- each round-tripped through `set`/`to_fen` (and
+    // `to_shredder_fen` for the 960 cases), in lieu of true property-based
+    // testing since this crate has no quickcheck/proptest dependency.
+    const ROUND_TRIP_FENS: [&'static str; 5] = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r1bqk2r/pp1n1ppp/2p1pn2/3p4/2PP4/2N1PN2/PP3PPP/R1BQKB1R w KQkq - 0 8",
+        "rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 3",
+        "1rkr2nr/8/8/8/8/8/8/1RKR2NR w HBhb - 0 1",
+        "nrk1r3/8/8/8/8/8/8/NRK1R3 w EBeb - 0 1",
+    ];
+
+    #[test]
+    fn test_to_fen_round_trips_for_every_sample() {
+        for &f in ROUND_TRIP_FENS.iter() {
+            // Only the Chess960 samples are parsed in Chess960 mode, so
+            // `to_fen` writes each back in its own castling style.
+            let pos = Position::set(f, !f.contains("KQkq")).unwrap();
+            assert_eq!(pos.to_fen(), f, "round-trip mismatch for {}", f);
+        }
+    }
+
+    #[test]
+    fn test_to_shredder_fen_round_trips_for_every_sample() {
+        for &f in ROUND_TRIP_FENS.iter() {
+            let pos = Position::set(f, true).unwrap();
+            let shredder = pos.to_shredder_fen();
+            assert_eq!(Position::set(&shredder, true).unwrap().to_fen(), pos.to_fen());
+        }
+    }
+
+    #[test]
+    fn test_to_fen_without_chess960_uses_kqkq_letters() {
+        let pos = Position::set("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", false).unwrap();
+        assert!(pos.to_fen().contains("KQkq"));
+        assert!(pos.to_shredder_fen().contains("HAha"));
+    }
+
+    #[test]
+    fn test_set_rejects_missing_king() {
+        match Position::set("rnbq1bnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQ1BNR w - - 0 1", false) {
+            Err(FenError::BadKingCount(WHITE)) => {}
+            Err(e) => panic!("expected BadKingCount(WHITE), got {:?}", e),
+            Ok(_) => panic!("expected BadKingCount(WHITE), got Ok"),
+        }
+    }
+
+    #[test]
+    fn test_set_rejects_two_kings_for_one_color() {
+        match Position::set("rnbqkbnr/ppppKppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", false) {
+            Err(FenError::BadKingCount(WHITE)) => {}
+            Err(e) => panic!("expected BadKingCount(WHITE), got {:?}", e),
+            Ok(_) => panic!("expected BadKingCount(WHITE), got Ok"),
+        }
+    }
+
+    #[test]
+    fn test_key_is_stable_across_equivalent_parses() {
+        let a = Position::set("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", false).unwrap();
+        let b = Position::set("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 12", false).unwrap();
+        assert_eq!(a.key(), b.key());
+    }
+
+    #[test]
+    fn test_key_differs_by_side_to_move() {
+        let white = Position::set("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", false).unwrap();
+        let black = Position::set("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1", false).unwrap();
+        assert!(white.key() != black.key());
+    }
+
+    #[test]
+    fn test_do_move_toggles_key_and_undo_move_restores_it() {
+        let mut pos = Position::set("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", false).unwrap();
+        let original_key = pos.key();
+
+        pos.do_move(make_move_simple(make_square(FILE_E, RANK_2), make_square(FILE_E, RANK_4)));
+        assert!(pos.key() != original_key);
+
+        pos.undo_move();
+        assert_eq!(pos.key(), original_key);
+    }
+
+    #[test]
+    fn test_see_undefended_pawn_capture_wins_its_value() {
+        let pos = Position::set("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1", false).unwrap();
+        let capture = make_move_simple(make_square(FILE_E, RANK_4), make_square(FILE_D, RANK_5));
+        assert_eq!(pos.see(capture), PAWN_VALUE_MG.0);
+    }
+
+    #[test]
+    fn test_see_pawn_recapture_makes_an_equal_pawn_trade() {
+        let pos = Position::set("4k3/8/2p5/3p4/4P3/8/8/4K3 w - - 0 1", false).unwrap();
+        let capture = make_move_simple(make_square(FILE_E, RANK_4), make_square(FILE_D, RANK_5));
+        assert_eq!(pos.see(capture), 0);
+    }
+
+    #[test]
+    fn test_see_capturing_a_defended_piece_with_a_pricier_attacker_loses_material() {
+        let pos = Position::set("k7/8/2p5/3p4/5N2/8/8/K7 w - - 0 1", false).unwrap();
+        let capture = make_move_simple(make_square(FILE_F, RANK_4), make_square(FILE_D, RANK_5));
+        assert_eq!(pos.see(capture), PAWN_VALUE_MG.0 - KNIGHT_VALUE_MG.0);
+    }
+
+    #[test]
+    fn test_see_follows_a_battery_through_an_xray_attacker() {
+        // White queen (front of an e-file battery) takes a knight defended
+        // by a rook; the rook recaptures the queen, at which point the
+        // white rook behind it -- only reachable once the queen has moved
+        // off the file -- recaptures the rook in turn.
+        let pos = Position::set("k3r3/8/8/4n3/8/8/4Q3/K3R3 w - - 0 1", false).unwrap();
+        let capture = make_move_simple(make_square(FILE_E, RANK_2), make_square(FILE_E, RANK_5));
+        let expected = KNIGHT_VALUE_MG.0 - QUEEN_VALUE_MG.0 + ROOK_VALUE_MG.0;
+        assert_eq!(pos.see(capture), expected);
+    }
+
+    #[test]
+    fn test_see_refuses_to_recapture_with_a_king_left_attacked() {
+        // White pawn takes a black pawn on d5 that's defended by two black
+        // pawns (c6 and e6); the only other white piece attacking d5 is
+        // the king on d4. After black's first pawn recaptures, the king
+        // would be the sole remaining white attacker, but e6 still guards
+        // d5 -- an illegal, unsafe recapture -- so the exchange must stop
+        // at the even pawn trade rather than let the king walk in.
+        let pos = Position::set("k7/8/2p1p3/3p4/3KP3/8/8/8 w - - 0 1", false).unwrap();
+        let capture = make_move_simple(make_square(FILE_E, RANK_4), make_square(FILE_D, RANK_5));
+        assert_eq!(pos.see(capture), 0);
+    }
+
+    #[test]
+    fn test_in_check_detects_the_side_to_move_in_check() {
+        // Black king on e8, white rook on e2, nothing blocking the e-file
+        // between them, black to move.
+        let pos = Position::set("4k3/8/8/8/8/8/4R3/4K3 b - - 0 1", false).unwrap();
+        assert!(pos.in_check());
+    }
+
+    #[test]
+    fn test_in_check_is_false_when_no_piece_attacks_the_king() {
+        let pos = Position::set("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", false).unwrap();
+        assert!(!pos.in_check());
+    }
+
+    #[test]
+    fn test_gives_check_detects_a_direct_rook_check() {
+        // Rook slides from a1 up the a-file onto the same rank as the
+        // black king, giving check along rank 8.
+        let pos = Position::set("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", false).unwrap();
+        let mv = make_move_simple(make_square(FILE_A, RANK_1), make_square(FILE_A, RANK_8));
+        assert!(pos.gives_check(mv));
+    }
+
+    #[test]
+    fn test_gives_check_detects_a_discovered_check() {
+        // White rook on e1 and black king on e8 share the e-file, with a
+        // white knight on e4 the only thing in between. Moving the knight
+        // off the file (not along the e1-e8 line) uncovers the rook's
+        // check.
+        let pos = Position::set("4k3/8/8/8/4N3/8/8/K3R3 w - - 0 1", false).unwrap();
+        let mv = make_move_simple(make_square(FILE_E, RANK_4), make_square(FILE_D, RANK_6));
+        assert!(pos.is_discovered_check_on_king(BLACK, mv));
+        assert!(pos.gives_check(mv));
+    }
+
+    #[test]
+    fn test_ensure_accumulator_refreshes_when_the_replayed_king_move_is_white() {
+        // White king steps e2-e3 with a stationary white knight on f1. The
+        // knight's own feature index is king-relative, so it's only valid
+        // under e2 at the root and under e3 after the king moves -- a
+        // replay that reuses the root's knight contribution unchanged
+        // (rather than refreshing the whole board) would diverge from a
+        // from-scratch recomputation.
+        let mut weights = Box::new(eval::FeatureWeights { weights: [[0i16; eval::ACCUMULATOR_SIZE]; eval::FEATURE_NB] });
+        weights.weights[eval::feature_index(WHITE, SQ_E2, W_KNIGHT, SQ_F1)][0] = 11;
+        weights.weights[eval::feature_index(WHITE, SQ_E3, W_KNIGHT, SQ_F1)][0] = 22;
+        weights.weights[eval::feature_index(WHITE, SQ_E2, W_KING, SQ_E2)][0] = 1;
+        weights.weights[eval::feature_index(WHITE, SQ_E3, W_KING, SQ_E3)][0] = 2;
+
+        let mut pos = Position::set("4k3/8/8/8/8/8/4K3/5N2 w - - 0 1", false).unwrap();
+        pos.ensure_accumulator(&weights);
+
+        pos.do_move(make_move_simple(SQ_E2, SQ_E3));
+        pos.ensure_accumulator(&weights);
+
+        let mut expected = eval::ACCUMULATOR_EMPTY;
+        eval::refresh_accumulator(&weights, &mut expected, WHITE, SQ_E3, &[(W_KING, SQ_E3), (W_KNIGHT, SQ_F1)]);
+
+        assert_eq!(
+            pos.st.as_ref().unwrap().accumulator.accumulation[WHITE.0 as usize],
+            expected.accumulation[WHITE.0 as usize]
+        );
+    }
+}
@@ -151,7 +151,11 @@ pub struct Bitboard(pub u64);
 enable_bitwise_operators_on! { Bitboard }
 
 pub const MAX_MOVES: i16 = 256;
-pub const MAX_PLY: i16   = 128;
+/// Search depth ceiling. Stockfish raised this from 128 to 246 to allow
+/// deeper searches; bumping it here is safe as long as the mate-score
+/// encoding below still round-trips, which the `_ASSERT_*` checks near
+/// `Depth` verify at compile time.
+pub const MAX_PLY: i16   = 246;
 
 ///-----------------------------------------------------------------------------
 /// A move needs 16 bits to be stored
@@ -195,6 +199,7 @@ const_vals! { Color:
     NO_COLOR = 2,
     COLOR_NB = 2
 }
+pub const COLOR_NB_USIZE: usize = COLOR_NB.0 as usize;
 impl Neg for Color {
     type Output = Color;
     fn neg(self) -> Color { Color(self.0 ^ BLACK.0) }
@@ -212,10 +217,7 @@ const_vals! { CastlingSide:
 impl BitOr<Color> for CastlingSide {
     type Output = CastlingRight;
     fn bitor(self, c: Color) -> CastlingRight {
-        match self == QUEEN_SIDE {
-            true => CastlingRight(WHITE_OO.0 << (1 + 2 * c.0)),
-            false => CastlingRight(WHITE_OO.0 << (0 + 2 * c.0))
-        }
+        make_castling(c, self)
     }
 }
 
@@ -232,16 +234,63 @@ const_vals! { CastlingRight:
     ANY_CASTLING = WHITE_OO.0 | WHITE_OOO.0 | BLACK_OO.0 | BLACK_OOO.0,
     CASTLING_RIGHT_NB = 16
 }
+pub const CASTLING_RIGHT_NB_USIZE: usize = CASTLING_RIGHT_NB.0 as usize;
+
+
+/// The `CastlingRight` bit belonging to `c`'s `s`, without branching: the
+/// four rights occupy bits `2*c + s` (`s` is 0 for `KING_SIDE`, 1 for
+/// `QUEEN_SIDE`), the same layout `WHITE_OO`/`WHITE_OOO`/`BLACK_OO`/
+/// `BLACK_OOO` already use. Replaces the old `C++` `MakeCastling` template.
+pub fn make_castling(c: Color, s: CastlingSide) -> CastlingRight {
+    CastlingRight(WHITE_OO.0 << (2 * c.0 as u16 + s.0))
+}
+
+/// Both of `c`'s castling rights, OR'd together.
+pub fn castling_right_for(c: Color) -> CastlingRight {
+    make_castling(c, KING_SIDE) | make_castling(c, QUEEN_SIDE)
+}
+
+/// Does `cr` include a king-side right (for either color)?
+pub fn king_side(cr: CastlingRight) -> bool {
+    cr & CastlingRight(WHITE_OO.0 | BLACK_OO.0) != CastlingRight(0)
+}
+
+/// Where the rook ends up for `cr` in standard (non-Chess960) castling.
+/// `cr` must be a single right (`WHITE_OO`, `WHITE_OOO`, `BLACK_OO` or
+/// `BLACK_OOO`); anything else returns `SQ_NONE`. Chess960 rook squares
+/// vary per game and live on `Position` instead -- this is the fixed
+/// standard-chess fallback.
+pub fn castling_rook_square(cr: CastlingRight) -> Square {
+    match cr {
+        WHITE_OO => SQ_F1,
+        WHITE_OOO => SQ_D1,
+        BLACK_OO => SQ_F8,
+        BLACK_OOO => SQ_D8,
+        _ => SQ_NONE,
+    }
+}
 
+/// Where the king ends up for `cr` in standard (non-Chess960) castling.
+pub fn castling_king_target(cr: CastlingRight) -> Square {
+    match cr {
+        WHITE_OO => SQ_G1,
+        WHITE_OOO => SQ_C1,
+        BLACK_OO => SQ_G8,
+        BLACK_OOO => SQ_C8,
+        _ => SQ_NONE,
+    }
+}
 
-// TODO: MakeCastling
-/*
-template<Color C, CastlingSide S> struct MakeCastling {
-  static const CastlingRight
-  right = C == WHITE ? S == QUEEN_SIDE ? WHITE_OOO : WHITE_OO
-                     : S == QUEEN_SIDE ? BLACK_OOO : BLACK_OO;
-};
-*/
+/// Which side(s) a king may still castle to, independent of color --
+/// paired with `bitboard::castling_target` to add the king's castling
+/// destinations to its normal move bitboard.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CastleRights {
+    NoSide,
+    KingSide,
+    QueenSide,
+    BothSides,
+}
 
 ///-----------------------------------------------------------------------------
 #[derive(Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Debug, Hash)]
@@ -254,6 +303,7 @@ const_vals! { Phase:
     EG = 1,
     PHASE_NB = 2
 }
+pub const PHASE_NB_USIZE: usize = PHASE_NB.0 as usize;
 
 ///-----------------------------------------------------------------------------
 #[derive(Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Debug, Hash)]
@@ -325,11 +375,12 @@ pub struct PieceType(pub i8);
 enable_bitwise_operators_on! { PieceType }
 enable_full_operators_on! { PieceType, i8 }
 enable_signed_operators_on! { PieceType }
-const_vals! { PieceType: 
+const_vals! { PieceType:
     NO_PIECE_TYPE = 0, PAWN = 1, KNIGHT = 2, BISHOP = 3, ROOK = 4, QUEEN = 5, KING = 6,
     ALL_PIECES = 0,
     PIECE_TYPE_NB = 8
 }
+pub const PIECE_TYPE_NB_USIZE: usize = PIECE_TYPE_NB.0 as usize;
 
 ///-----------------------------------------------------------------------------
 #[derive(Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Debug, Hash)]
@@ -342,6 +393,7 @@ const_vals! { Piece:
     B_PAWN = 9, B_KNIGHT = 10, B_BISHOP = 11, B_ROOK = 12, B_QUEEN = 13, B_KING = 14,
     PIECE_NB = 16
 }
+pub const PIECE_NB_USIZE: usize = PIECE_NB.0 as usize;
 
 impl Neg for Piece {
     type Output = Piece;
@@ -350,8 +402,47 @@ impl Neg for Piece {
 
 pub static PIECES: &'static [Piece] = &[ W_PAWN, W_KNIGHT, W_BISHOP, W_ROOK, W_QUEEN, W_KING,
                          B_PAWN, B_KNIGHT, B_BISHOP, B_ROOK, B_QUEEN, B_KING ];
-// TODO: 
-// extern Value PieceValue[PHASE_NB][PIECE_NB];
+
+/// Middlegame/endgame value for every `Piece` slot, built from
+/// `PAWN_VALUE_MG`..`QUEEN_VALUE_EG` above. `NO_PIECE`, both kings, and
+/// the unused slots between the color halves are all `VALUE_ZERO`. White
+/// and black pieces of the same type share a value, since `PieceType`'s
+/// indices line up with white's `Piece` indices this also doubles as the
+/// `PieceType` table `piece_type_value` reads from.
+pub static PIECE_VALUE: [[Value; PIECE_NB_USIZE]; PHASE_NB_USIZE] = [
+    [
+        VALUE_ZERO,
+        PAWN_VALUE_MG, KNIGHT_VALUE_MG, BISHOP_VALUE_MG, ROOK_VALUE_MG, QUEEN_VALUE_MG, VALUE_ZERO,
+        VALUE_ZERO,
+        VALUE_ZERO,
+        PAWN_VALUE_MG, KNIGHT_VALUE_MG, BISHOP_VALUE_MG, ROOK_VALUE_MG, QUEEN_VALUE_MG, VALUE_ZERO,
+        VALUE_ZERO,
+    ],
+    [
+        VALUE_ZERO,
+        PAWN_VALUE_EG, KNIGHT_VALUE_EG, BISHOP_VALUE_EG, ROOK_VALUE_EG, QUEEN_VALUE_EG, VALUE_ZERO,
+        VALUE_ZERO,
+        VALUE_ZERO,
+        PAWN_VALUE_EG, KNIGHT_VALUE_EG, BISHOP_VALUE_EG, ROOK_VALUE_EG, QUEEN_VALUE_EG, VALUE_ZERO,
+        VALUE_ZERO,
+    ],
+];
+
+pub fn piece_value(phase: Phase, pc: Piece) -> Value {
+    PIECE_VALUE[phase.0 as usize][pc.0 as usize]
+}
+
+pub fn piece_type_value(phase: Phase, pt: PieceType) -> Value {
+    PIECE_VALUE[phase.0 as usize][pt.0 as usize]
+}
+
+/// Packed mg/eg `Score` for `pt`, read from the same `PIECE_VALUE` table
+/// `piece_value`/`piece_type_value` use, so material counting and SEE
+/// share one source of truth for piece values instead of each keeping
+/// its own packed copy.
+pub fn piece_type_score(pt: PieceType) -> Score {
+    make_score(piece_type_value(MG, pt).0 as u32, piece_type_value(EG, pt).0 as u32)
+}
 
 ///-----------------------------------------------------------------------------
 #[derive(Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Debug, Hash)]
@@ -371,6 +462,36 @@ const_vals! { Depth:
     DEPTH_MAX  = MAX_PLY * ONE_PLY.0
 }
 
+// Static asserts (the classic zero-size-array trick, since this predates
+// `const_assert!`/`static_assertions`): if `MAX_PLY` is ever raised again
+// these catch the two ways it could silently break mate-score encoding
+// before it ships -- `VALUE_MATE_IN_MAX_PLY` drifting to (or past) zero,
+// and `DEPTH_MAX` overflowing `Depth`'s `i16`.
+#[allow(dead_code)]
+const _ASSERT_MATE_IN_MAX_PLY_POSITIVE: [(); 1] =
+    [(); (VALUE_MATE_IN_MAX_PLY.0 > 0) as usize];
+#[allow(dead_code)]
+const _ASSERT_MATED_IN_MAX_PLY_NEGATIVE: [(); 1] =
+    [(); (VALUE_MATED_IN_MAX_PLY.0 < 0) as usize];
+#[allow(dead_code)]
+const _ASSERT_DEPTH_MAX_FITS_I16: [(); 1] =
+    [(); ((MAX_PLY as i32) * (ONE_PLY.0 as i32) <= i16::max_value() as i32) as usize];
+
+///-----------------------------------------------------------------------------
+/// Distance from the search root, in plies. Kept separate from `Depth`
+/// (which also represents negative quiescence depths) because a ply
+/// count is always non-negative and always bounded by the configured
+/// `MAX_PLY` -- `new` enforces both so mate-score encoding never sees an
+/// out-of-range ply.
+#[derive(Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Debug, Hash)]
+pub struct Ply(pub i16);
+
+impl Ply {
+    pub fn new(p: i16) -> Ply {
+        Ply(::std::cmp::max(0, ::std::cmp::min(p, MAX_PLY)))
+    }
+}
+
 ///-----------------------------------------------------------------------------
 #[derive(Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Debug, Hash)]
 pub struct Square(pub i8);
@@ -399,6 +520,7 @@ const_vals! { Square:
     SOUTH_WEST = SOUTH.0 + WEST.0,
     NORTH_WEST = NORTH.0 + WEST.0
 }
+pub const SQUARE_NB_USIZE: usize = SQUARE_NB.0 as usize;
 
 impl Neg for Square {
     type Output = Square;
@@ -426,6 +548,7 @@ const_vals! { File:
     FILE_A=0, FILE_B=1, FILE_C=2, FILE_D=3, FILE_E=4, FILE_F=5, FILE_G=6, FILE_H=7, FILE_NB=8
 }
 pub static FILES: &'static [File] = &[ FILE_A, FILE_B, FILE_C, FILE_D, FILE_E, FILE_F, FILE_G, FILE_H ];
+pub const FILE_NB_USIZE: usize = FILE_NB.0 as usize;
 
 ///-----------------------------------------------------------------------------
 #[derive(Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Debug, Hash)]
@@ -474,13 +597,52 @@ impl Div<u32> for Score {
     }
 }
 
+/// `mg_value`/`eg_value` above return the raw 16-bit field unsigned, so a
+/// negative endgame value reads back as a large positive one. These
+/// sign-extend instead, which `Score::scale` needs to multiply correctly.
+fn mg_value_signed(s: Score) -> i32 {
+    (s.0 as u16) as i16 as i32
+}
+
+fn eg_value_signed(s: Score) -> i32 {
+    ((s.0 >> 16) as u16) as i16 as i32
+}
+
+impl Score {
+    /// Multiply the middlegame and endgame halves by `weight`
+    /// independently, so a carry out of one 16-bit half can never bleed
+    /// into the other the way a naive `u32` multiply would. This is the
+    /// scaling `Mul` was left out above to avoid.
+    ///
+    /// In debug builds, guards against the overflow described in
+    /// Stockfish issue #969: repacks the two products and decomposes them
+    /// back out, asserting the round trip reproduces the intended values
+    /// (if a half overflowed `i16`, it wouldn't).
+    pub fn scale(self, weight: i32) -> Score {
+        let mg = mg_value_signed(self) * weight;
+        let eg = eg_value_signed(self) * weight;
+        let result = make_score((mg as i16 as u16) as u32, (eg as i16 as u16) as u32);
+        debug_assert_eq!(mg_value_signed(result), mg,
+            "Score::scale: middlegame half overflowed i16");
+        debug_assert_eq!(eg_value_signed(result), eg,
+            "Score::scale: endgame half overflowed i16");
+        result
+    }
+}
+
 ///-----------------------------------------------------------------------------
-pub fn mate_in(ply: i32) -> Value {
-    return VALUE_MATE - ply;
+/// Score for delivering mate in `ply` plies, clamped to `[0, MAX_PLY]`
+/// first -- a ply count from beyond the configured search horizon would
+/// otherwise encode a mate score `mate_in`/`mated_in` can't tell apart
+/// from a shallower one.
+pub fn mate_in(ply: Ply) -> Value {
+    let p = Ply::new(ply.0);
+    VALUE_MATE - p.0 as i32
 }
 
-pub fn mated_in(ply: i32) -> Value {
-    return -VALUE_MATE + ply;
+pub fn mated_in(ply: Ply) -> Value {
+    let p = Ply::new(ply.0);
+    -VALUE_MATE + p.0 as i32
 }
 
 pub fn make_square(f: File, r: Rank) -> Square {
@@ -512,6 +674,31 @@ pub fn rank_of(s: Square) -> Rank {
     return Rank(s.0 >> 3);
 }
 
+impl Square {
+    /// One step in direction `(df, dr)`, or `None` if either the file or
+    /// the rank would fall off the board -- `is_square_ok` alone isn't
+    /// enough here, since e.g. `SQ_H4 + 1` is still a valid square index,
+    /// just the wrong one (it wraps to `SQ_A5`).
+    fn step(self, df: i8, dr: i8) -> Option<Square> {
+        let f = file_of(self).0 + df;
+        let r = rank_of(self).0 + dr;
+        if f < 0 || f > 7 || r < 0 || r > 7 {
+            return None;
+        }
+        let target = make_square(File(f), Rank(r));
+        if is_square_ok(target) { Some(target) } else { None }
+    }
+
+    pub fn up(self) -> Option<Square> { self.step(0, 1) }
+    pub fn down(self) -> Option<Square> { self.step(0, -1) }
+    pub fn left(self) -> Option<Square> { self.step(-1, 0) }
+    pub fn right(self) -> Option<Square> { self.step(1, 0) }
+    pub fn up_left(self) -> Option<Square> { self.step(-1, 1) }
+    pub fn up_right(self) -> Option<Square> { self.step(1, 1) }
+    pub fn down_left(self) -> Option<Square> { self.step(-1, -1) }
+    pub fn down_right(self) -> Option<Square> { self.step(1, -1) }
+}
+
 /// The relative functions give you the equivalent square / rank to the one
 /// passed in if you were sitting on the color's side of the board. Sometimes
 /// this is the same square / rank you passed in
@@ -564,7 +751,7 @@ pub fn promotion_type(m: Move) -> PieceType {
 
 
 // TODO: These could probably be optimized and compile time checked
-pub fn make_move_with_promotion(from: Square, to: Square, pt: PieceType) -> Move {
+pub fn make_move_promotion(from: Square, to: Square, pt: PieceType) -> Move {
     let from = from.0 as u16;
     let to = to.0 as u16;
     let pt = pt.0 as u16;
@@ -573,21 +760,34 @@ pub fn make_move_with_promotion(from: Square, to: Square, pt: PieceType) -> Move
     return Move(promotion | ((pt - knight) << 12) | (from << 6) | to);
 }
 
-pub fn make_move_(from: Square, to: Square) -> Move {
+pub fn make_move_enpassant(from: Square, to: Square) -> Move {
     let from = from.0 as u16;
     let to = to.0 as u16;
     let enpassant = ENPASSANT.0 as u16;
     return Move(enpassant | ((from << 6) | to));
 }
 
-pub fn make_castling_move(from: Square, to: Square) -> Move {
+pub fn make_move_castling(from: Square, to: Square) -> Move {
     let from = from.0 as u16;
     let to = to.0 as u16;
     let castling = CASTLING.0 as u16;
     return Move(castling | ((from << 6) | to));
 }
+
+/// Beyond the `MOVE_NULL`/`MOVE_NONE` check, a promotion move's packed
+/// selector must decode to one of the four promotable piece types, and
+/// only a promotion move carries one at all -- catches a move word with
+/// the `PROMOTION` tag but a stray high bit that wouldn't otherwise be
+/// caught by `from_square`/`to_square` alone.
 pub fn is_move_ok(m: Move) -> bool {
-    return from_square(m) != to_square(m); // Catch MOVE_NULL and MOVE_NONE
+    if from_square(m) == to_square(m) {
+        return false; // Catch MOVE_NULL and MOVE_NONE
+    }
+    if type_of_move(m) == PROMOTION {
+        let pt = promotion_type(m);
+        return pt >= KNIGHT && pt <= QUEEN;
+    }
+    true
 }
 
 ///-----------------------------------------------------------------------------
@@ -600,6 +800,23 @@ mod tests {
         assert!((!(ONE_PLY & (ONE_PLY - Depth(1)))).0 != 0, "ONE_PLY is not a power of 2");
     }
 
+    #[test]
+    fn test_ply_clamps_to_max_ply() {
+        assert_eq!(Ply(0), Ply::new(-1));
+        assert_eq!(Ply(0), Ply::new(0));
+        assert_eq!(Ply(MAX_PLY), Ply::new(MAX_PLY));
+        assert_eq!(Ply(MAX_PLY), Ply::new(MAX_PLY + 1));
+    }
+
+    #[test]
+    fn test_mate_in_and_mated_in_clamp_out_of_range_plies() {
+        assert_eq!(mate_in(Ply(0)), VALUE_MATE);
+        assert_eq!(mate_in(Ply(MAX_PLY + 10)), mate_in(Ply(MAX_PLY)));
+
+        assert_eq!(mated_in(Ply(0)), -VALUE_MATE);
+        assert_eq!(mated_in(Ply(MAX_PLY + 10)), mated_in(Ply(MAX_PLY)));
+    }
+
     #[test]
     fn test_make_score() {
         assert_eq!(
@@ -759,6 +976,29 @@ mod tests {
         assert_eq!(KING, type_of_piece(B_KING));
     }
 
+    #[test]
+    fn test_piece_value_symmetric_across_colors() {
+        for &(wp, bp) in &[(W_PAWN, B_PAWN), (W_KNIGHT, B_KNIGHT), (W_BISHOP, B_BISHOP),
+                            (W_ROOK, B_ROOK), (W_QUEEN, B_QUEEN), (W_KING, B_KING)] {
+            assert_eq!(piece_value(MG, wp), piece_value(MG, bp));
+            assert_eq!(piece_value(EG, wp), piece_value(EG, bp));
+        }
+
+        assert_eq!(VALUE_ZERO, piece_value(MG, NO_PIECE));
+        assert_eq!(VALUE_ZERO, piece_value(MG, W_KING));
+        assert_eq!(VALUE_ZERO, piece_value(MG, B_KING));
+
+        assert_eq!(PAWN_VALUE_MG, piece_type_value(MG, PAWN));
+        assert_eq!(QUEEN_VALUE_EG, piece_type_value(EG, QUEEN));
+    }
+
+    #[test]
+    fn test_piece_type_score_packs_piece_value() {
+        let score = piece_type_score(ROOK);
+        assert_eq!(mg_value(score), Score(ROOK_VALUE_MG.0 as u32));
+        assert_eq!(eg_value(score), Score(ROOK_VALUE_EG.0 as u32));
+    }
+
     #[test]
     fn test_color_of() {
         assert_eq!(WHITE, color_of(W_PAWN));
@@ -803,6 +1043,30 @@ mod tests {
         assert_eq!(false, is_square_ok(Square(SQ_H8.0+1)));
     }
 
+    #[test]
+    fn test_square_stepping_from_center() {
+        assert_eq!(Some(SQ_E5), SQ_E4.up());
+        assert_eq!(Some(SQ_E3), SQ_E4.down());
+        assert_eq!(Some(SQ_D4), SQ_E4.left());
+        assert_eq!(Some(SQ_F4), SQ_E4.right());
+        assert_eq!(Some(SQ_D5), SQ_E4.up_left());
+        assert_eq!(Some(SQ_F5), SQ_E4.up_right());
+        assert_eq!(Some(SQ_D3), SQ_E4.down_left());
+        assert_eq!(Some(SQ_F3), SQ_E4.down_right());
+    }
+
+    #[test]
+    fn test_square_stepping_off_board_is_none() {
+        assert_eq!(None, SQ_H4.right());
+        assert_eq!(None, SQ_A4.left());
+        assert_eq!(None, SQ_E8.up());
+        assert_eq!(None, SQ_E1.down());
+        assert_eq!(None, SQ_H8.up_right());
+        assert_eq!(None, SQ_A1.down_left());
+        assert_eq!(None, SQ_H1.down_right());
+        assert_eq!(None, SQ_A8.up_left());
+    }
+
     #[test]
     fn test_file_of() {
         for &f in FILES {
@@ -919,4 +1183,31 @@ mod tests {
         assert_eq!(false, is_move_ok(MOVE_NULL));
         assert_eq!(false, is_move_ok(MOVE_NONE));
     }
+
+    #[test]
+    fn test_make_move_promotion_round_trips() {
+        for &pt in &[KNIGHT, BISHOP, ROOK, QUEEN] {
+            let m = make_move_promotion(SQ_A7, SQ_A8, pt);
+            assert_eq!(SQ_A7, from_square(m));
+            assert_eq!(SQ_A8, to_square(m));
+            assert_eq!(PROMOTION, type_of_move(m));
+            assert_eq!(pt, promotion_type(m));
+            assert_eq!(true, is_move_ok(m));
+        }
+    }
+
+    #[test]
+    fn test_make_move_enpassant_and_castling_round_trip() {
+        let ep = make_move_enpassant(SQ_E5, SQ_D6);
+        assert_eq!(SQ_E5, from_square(ep));
+        assert_eq!(SQ_D6, to_square(ep));
+        assert_eq!(ENPASSANT, type_of_move(ep));
+        assert_eq!(true, is_move_ok(ep));
+
+        let castle = make_move_castling(SQ_E1, SQ_G1);
+        assert_eq!(SQ_E1, from_square(castle));
+        assert_eq!(SQ_G1, to_square(castle));
+        assert_eq!(CASTLING, type_of_move(castle));
+        assert_eq!(true, is_move_ok(castle));
+    }
 }
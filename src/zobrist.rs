@@ -0,0 +1,269 @@
+// This file is part of the samson library.
+//
+// Copyright (C) 2017 Lakin Wecker <lakin@wecker.ca>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+///-----------------------------------------------------------------------------
+/// Zobrist hashing, patterned after Stockfish's `Zobrist` namespace: a
+/// fixed table of random keys, one per (piece, square), plus side-to-move,
+/// castling-rights, and en-passant-file keys, all XORed together to fold a
+/// position down to a single `Key`. Callers that already track *what*
+/// changed on a move (a dirty piece list, a captured piece, a toggled
+/// castling right) should XOR the individual keys below in and out rather
+/// than calling `compute` again.
+///-----------------------------------------------------------------------------
+
+use types::*;
+
+/// Stockfish's `xorshift64star`, re-seeded independently of
+/// `bitboard::magic`'s copy -- this module has no business reaching into a
+/// sibling module's private PRNG, and the two are seeded differently
+/// anyway.
+struct Prng {
+    state: u64,
+}
+
+impl Prng {
+    fn new(seed: u64) -> Prng {
+        Prng { state: seed }
+    }
+
+    fn rand64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545F4914F6CDD1Du64)
+    }
+}
+
+/// The full set of random keys a position's hash is built from.
+struct Tables {
+    piece_square: [[Key; SQUARE_NB_USIZE]; PIECE_NB_USIZE],
+    side: Key,
+    castling: [Key; CASTLING_RIGHT_NB_USIZE],
+    en_passant: [Key; FILE_NB_USIZE],
+}
+
+fn init() -> Tables {
+    let mut rng = Prng::new(0x9D39_247E_33776D41u64);
+
+    let mut piece_square = [[Key(0); SQUARE_NB_USIZE]; PIECE_NB_USIZE];
+    for pc in 0..PIECE_NB_USIZE {
+        for sq in 0..SQUARE_NB_USIZE {
+            piece_square[pc][sq] = Key(rng.rand64());
+        }
+    }
+
+    // Each castling right is the XOR of whichever of the four single-right
+    // base keys its bits name, so that e.g. losing just `WHITE_OOO` (and
+    // leaving `WHITE_OO` untouched) still lands on a distinct key rather
+    // than needing all 16 combinations drawn independently.
+    let base = [Key(rng.rand64()), Key(rng.rand64()), Key(rng.rand64()), Key(rng.rand64())];
+    let rights = [WHITE_OO, WHITE_OOO, BLACK_OO, BLACK_OOO];
+    let mut castling = [Key(0); CASTLING_RIGHT_NB_USIZE];
+    for cr in 0..CASTLING_RIGHT_NB_USIZE {
+        let mut key = Key(0);
+        for (i, &right) in rights.iter().enumerate() {
+            if cr as i32 & right.0 as i32 != 0 {
+                key = key ^ base[i];
+            }
+        }
+        castling[cr] = key;
+    }
+
+    let mut en_passant = [Key(0); FILE_NB_USIZE];
+    for file in 0..FILE_NB_USIZE {
+        en_passant[file] = Key(rng.rand64());
+    }
+
+    Tables {
+        piece_square: piece_square,
+        side: Key(rng.rand64()),
+        castling: castling,
+        en_passant: en_passant,
+    }
+}
+
+lazy_static! {
+    static ref TABLES: Tables = init();
+}
+
+/// The key for `pc` sitting on `sq`. XOR this in when placing `pc` on
+/// `sq`, and XOR it out again when removing it -- the same key serves
+/// both directions.
+pub fn piece_square(pc: Piece, sq: Square) -> Key {
+    TABLES.piece_square[pc.0 as usize][sq.0 as usize]
+}
+
+/// The key toggled whenever the side to move changes.
+pub fn side_to_move() -> Key {
+    TABLES.side
+}
+
+/// The key for the castling-rights mask `cr` (a bitwise-OR of
+/// `WHITE_OO`/`WHITE_OOO`/`BLACK_OO`/`BLACK_OOO`, as stored in
+/// `Position`'s `castling_rights` field). XOR the old mask's key out and
+/// the new mask's key in when rights change.
+pub fn castling_rights(cr: i32) -> Key {
+    TABLES.castling[cr as usize]
+}
+
+/// The key for an en-passant target on `file`. XOR this in only while an
+/// en-passant capture is actually available on that file, and out again
+/// the moment it stops being available.
+pub fn en_passant(file: File) -> Key {
+    TABLES.en_passant[file.0 as usize]
+}
+
+/// A position's key from scratch: the XOR of every occupied square's
+/// piece key, the side-to-move key (only when Black is to move -- White
+/// contributes nothing, following Stockfish's convention), the current
+/// castling-rights key, and the en-passant-file key if `ep_square` names
+/// one. Used to seed a freshly-`set` position; incremental updates from
+/// then on should toggle individual keys instead of calling this again.
+pub fn compute(board: &[Piece; SQUARE_NB_USIZE], side: Color, castling_rights_mask: i32, ep_square: Square) -> Key {
+    let mut key = Key(0);
+    for sq in 0..SQUARE_NB_USIZE {
+        let pc = board[sq];
+        if pc != NO_PIECE {
+            key = key ^ piece_square(pc, Square(sq as i8));
+        }
+    }
+    if side == BLACK {
+        key = key ^ side_to_move();
+    }
+    key = key ^ castling_rights(castling_rights_mask);
+    if ep_square != SQ_NONE {
+        key = key ^ en_passant(file_of(ep_square));
+    }
+    key
+}
+
+/// A position's key restricted to pawns only, for a future pawn-hash
+/// table: the XOR of the piece keys for every pawn (of either color) on
+/// the board.
+pub fn pawn_key(board: &[Piece; SQUARE_NB_USIZE]) -> Key {
+    let mut key = Key(0);
+    for sq in 0..SQUARE_NB_USIZE {
+        let pc = board[sq];
+        if pc != NO_PIECE && type_of_piece(pc) == PAWN {
+            key = key ^ piece_square(pc, Square(sq as i8));
+        }
+    }
+    key
+}
+
+/// Stockfish's trick for the material-entry cache: the piece-square
+/// table has 64 rows per piece but no position ever has more than ~10 of
+/// any one piece, so reusing it with `count` (rather than a square) as
+/// the index gives each (piece, count) pair its own key for free, XORed
+/// together across 0..count to get an order-independent material hash.
+pub fn material(pc: Piece, count: i32) -> Key {
+    let mut key = Key(0);
+    for n in 0..count {
+        key = key ^ TABLES.piece_square[pc.0 as usize][n as usize];
+    }
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn starting_board() -> [Piece; SQUARE_NB_USIZE] {
+        let mut board = [NO_PIECE; SQUARE_NB_USIZE];
+        let back_rank = [ROOK, KNIGHT, BISHOP, QUEEN, KING, BISHOP, KNIGHT, ROOK];
+        for file in 0..8 {
+            board[make_square(File(file as i8), RANK_1).0 as usize] = make_piece(WHITE, back_rank[file]);
+            board[make_square(File(file as i8), RANK_2).0 as usize] = make_piece(WHITE, PAWN);
+            board[make_square(File(file as i8), RANK_7).0 as usize] = make_piece(BLACK, PAWN);
+            board[make_square(File(file as i8), RANK_8).0 as usize] = make_piece(BLACK, back_rank[file]);
+        }
+        board
+    }
+
+    #[test]
+    fn test_compute_is_deterministic() {
+        let board = starting_board();
+        let a = compute(&board, WHITE, ANY_CASTLING.0 as i32, SQ_NONE);
+        let b = compute(&board, WHITE, ANY_CASTLING.0 as i32, SQ_NONE);
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn test_side_to_move_changes_the_key() {
+        let board = starting_board();
+        let white = compute(&board, WHITE, ANY_CASTLING.0 as i32, SQ_NONE);
+        let black = compute(&board, BLACK, ANY_CASTLING.0 as i32, SQ_NONE);
+        assert!(white != black);
+    }
+
+    #[test]
+    fn test_incremental_move_and_unmove_matches_full_recompute() {
+        let mut board = starting_board();
+        let mut key = compute(&board, WHITE, ANY_CASTLING.0 as i32, SQ_NONE);
+
+        // 1. e4, maintaining `key` incrementally the way `Position::do_move`
+        // does: XOR the moved piece out of its old square and into its new
+        // one, then flip the side to move.
+        let from = make_square(FILE_E, RANK_2);
+        let to = make_square(FILE_E, RANK_4);
+        let pawn = board[from.0 as usize];
+        key = key ^ piece_square(pawn, from) ^ piece_square(pawn, to) ^ side_to_move();
+        board[to.0 as usize] = pawn;
+        board[from.0 as usize] = NO_PIECE;
+
+        let recomputed = compute(&board, BLACK, ANY_CASTLING.0 as i32, SQ_NONE);
+        assert_eq!(key, recomputed);
+
+        // Unmake, again only toggling the keys that actually changed.
+        key = key ^ piece_square(pawn, from) ^ piece_square(pawn, to) ^ side_to_move();
+        board[from.0 as usize] = pawn;
+        board[to.0 as usize] = NO_PIECE;
+
+        let original = compute(&board, WHITE, ANY_CASTLING.0 as i32, SQ_NONE);
+        assert_eq!(key, original);
+    }
+
+    #[test]
+    fn test_repeated_positions_collide_by_key() {
+        // Two independently-assembled boards for the same position (as
+        // would arise from a repeated sequence of moves, e.g. knights
+        // shuffling back and forth) must hash identically so
+        // `Position::compute_repetition` can spot them by key equality.
+        let board_a = starting_board();
+        let mut board_b = starting_board();
+        let knight_from = make_square(FILE_B, RANK_1);
+        let knight_to = make_square(FILE_C, RANK_3);
+        let knight = board_b[knight_from.0 as usize];
+        board_b[knight_to.0 as usize] = knight;
+        board_b[knight_from.0 as usize] = NO_PIECE;
+        board_b[knight_from.0 as usize] = knight;
+        board_b[knight_to.0 as usize] = NO_PIECE;
+
+        let key_a = compute(&board_a, WHITE, ANY_CASTLING.0 as i32, SQ_NONE);
+        let key_b = compute(&board_b, WHITE, ANY_CASTLING.0 as i32, SQ_NONE);
+        assert_eq!(key_a, key_b);
+    }
+
+    #[test]
+    fn test_material_key_is_deterministic_and_differs_by_count() {
+        let white_pawn = make_piece(WHITE, PAWN);
+        let a = material(white_pawn, 3);
+        let b = material(white_pawn, 3);
+        assert_eq!(a, b);
+        assert!(material(white_pawn, 3) != material(white_pawn, 4));
+    }
+}